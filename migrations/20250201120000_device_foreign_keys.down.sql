@@ -0,0 +1,22 @@
+ALTER TABLE `device_types` RENAME TO `device_types_new`;
+CREATE TABLE `device_types`(
+    `device_id` BLOB NOT NULL,
+    `type_id` BLOB NOT NULL,
+    FOREIGN KEY(`device_id`) REFERENCES device(`id`),
+    FOREIGN KEY(`type_id`) REFERENCES types(`id`),
+    UNIQUE(`device_id`,`type_id`)
+);
+INSERT INTO `device_types` SELECT `device_id`, `type_id` FROM `device_types_new`;
+DROP TABLE `device_types_new`;
+
+ALTER TABLE `user_devices` RENAME TO `user_devices_new`;
+CREATE TABLE `user_devices`(
+    `user_id` BLOB NOT NULL,
+    `device_id` BLOB NOT NULL,
+    `visible` BOOLEAN NOT NULL DEFAULT 1,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`),
+    FOREIGN KEY(`device_id`) REFERENCES device(`id`),
+    UNIQUE(`user_id`, `device_id`)
+);
+INSERT INTO `user_devices` SELECT `user_id`, `device_id`, `visible` FROM `user_devices_new`;
+DROP TABLE `user_devices_new`;
@@ -0,0 +1,23 @@
+-- `device_types` and `user_devices` referenced the non existing `device` table
+ALTER TABLE `device_types` RENAME TO `device_types_old`;
+CREATE TABLE `device_types`(
+    `device_id` BLOB NOT NULL,
+    `type_id` BLOB NOT NULL,
+    FOREIGN KEY(`device_id`) REFERENCES devices(`id`) ON DELETE CASCADE,
+    FOREIGN KEY(`type_id`) REFERENCES types(`id`) ON DELETE CASCADE,
+    UNIQUE(`device_id`,`type_id`)
+);
+INSERT INTO `device_types` SELECT `device_id`, `type_id` FROM `device_types_old`;
+DROP TABLE `device_types_old`;
+
+ALTER TABLE `user_devices` RENAME TO `user_devices_old`;
+CREATE TABLE `user_devices`(
+    `user_id` BLOB NOT NULL,
+    `device_id` BLOB NOT NULL,
+    `visible` BOOLEAN NOT NULL DEFAULT 1,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE,
+    FOREIGN KEY(`device_id`) REFERENCES devices(`id`) ON DELETE CASCADE,
+    UNIQUE(`user_id`, `device_id`)
+);
+INSERT INTO `user_devices` SELECT `user_id`, `device_id`, `visible` FROM `user_devices_old`;
+DROP TABLE `user_devices_old`;
@@ -0,0 +1,2 @@
+-- encrypted SecureOn password, see `wol::secure_on::SecureOnCipher`
+ALTER TABLE `devices` ADD COLUMN `secure_on` BLOB NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE `devices` DROP COLUMN `interface`;
+ALTER TABLE `devices` DROP COLUMN `wol_port`;
+ALTER TABLE `devices` DROP COLUMN `broadcast_address`;
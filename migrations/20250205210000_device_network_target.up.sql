@@ -0,0 +1,4 @@
+-- per device override of the `[wol]` settings, NULL means use the global value
+ALTER TABLE `devices` ADD COLUMN `broadcast_address` TEXT NULL;
+ALTER TABLE `devices` ADD COLUMN `wol_port` INTEGER NULL;
+ALTER TABLE `devices` ADD COLUMN `interface` TEXT NULL;
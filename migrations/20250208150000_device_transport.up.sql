@@ -0,0 +1,2 @@
+-- `udp` or `ethernet`, see `wol::Transport`
+ALTER TABLE `devices` ADD COLUMN `transport` TEXT NOT NULL DEFAULT 'udp';
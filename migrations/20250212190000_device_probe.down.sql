@@ -0,0 +1,5 @@
+ALTER TABLE `devices` DROP COLUMN `last_seen`;
+ALTER TABLE `devices` DROP COLUMN `probe_interval`;
+ALTER TABLE `devices` DROP COLUMN `probe_port`;
+ALTER TABLE `devices` DROP COLUMN `probe_method`;
+ALTER TABLE `devices` DROP COLUMN `ip_address`;
@@ -0,0 +1,6 @@
+-- reachability prober, NULL means use the `[prober]` settings
+ALTER TABLE `devices` ADD COLUMN `ip_address` TEXT NULL;
+ALTER TABLE `devices` ADD COLUMN `probe_method` TEXT NULL;
+ALTER TABLE `devices` ADD COLUMN `probe_port` INTEGER NULL;
+ALTER TABLE `devices` ADD COLUMN `probe_interval` INTEGER NULL;
+ALTER TABLE `devices` ADD COLUMN `last_seen` DATETIME NULL;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS `device_boot_times_device`;
+DROP TABLE IF EXISTS `device_boot_times`;
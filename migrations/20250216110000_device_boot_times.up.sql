@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS `device_boot_times`(
+    `device_id` BLOB NOT NULL,
+    `duration_ms` INTEGER NOT NULL,
+    `recorded_at` DATETIME NOT NULL,
+    FOREIGN KEY(`device_id`) REFERENCES devices(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `device_boot_times_device` ON `device_boot_times`(`device_id`, `recorded_at`);
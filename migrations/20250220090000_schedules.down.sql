@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS `schedule_runs_schedule`;
+DROP TABLE IF EXISTS `schedule_runs`;
+DROP INDEX IF EXISTS `schedules_next_run`;
+DROP TABLE IF EXISTS `schedules`;
@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS `schedules`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    `device_id` BLOB NOT NULL,
+    -- 5 (minute precision) or 6/7 fields (seconds and year) cron expression
+    `cron` TEXT NOT NULL,
+    -- IANA time zone name the cron expression is evaluated in
+    `timezone` TEXT NOT NULL DEFAULT 'UTC',
+    `enabled` BOOLEAN NOT NULL DEFAULT 1,
+    `next_run` DATETIME NULL,
+    `created_at` DATETIME NOT NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE,
+    FOREIGN KEY(`device_id`) REFERENCES devices(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `schedules_next_run` ON `schedules`(`enabled`, `next_run`);
+
+CREATE TABLE IF NOT EXISTS `schedule_runs`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `schedule_id` BLOB NOT NULL,
+    `ran_at` DATETIME NOT NULL,
+    `success` BOOLEAN NOT NULL,
+    `error` TEXT NULL,
+    FOREIGN KEY(`schedule_id`) REFERENCES schedules(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `schedule_runs_schedule` ON `schedule_runs`(`schedule_id`, `ran_at`);
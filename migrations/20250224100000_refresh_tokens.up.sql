@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS `refresh_tokens`(
+    `jti` BLOB PRIMARY KEY NOT NULL,
+    -- tokens rotated from the same login share the family, reusing a rotated token revokes all of them
+    `family_id` BLOB NOT NULL,
+    `user_id` BLOB NOT NULL,
+    `issued_at` DATETIME NOT NULL,
+    `expires_at` DATETIME NOT NULL,
+    `rotated_at` DATETIME NULL,
+    `revoked_at` DATETIME NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `refresh_tokens_family` ON `refresh_tokens`(`family_id`);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS `sessions_user`;
+DROP TABLE IF EXISTS `sessions`;
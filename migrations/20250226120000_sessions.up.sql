@@ -0,0 +1,16 @@
+-- one session per login, its id is the `refresh_tokens.family_id`
+CREATE TABLE IF NOT EXISTS `sessions`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    `user_agent` TEXT NULL,
+    `ip_address` TEXT NULL,
+    `created_at` DATETIME NOT NULL,
+    `last_used_at` DATETIME NOT NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `sessions_user` ON `sessions`(`user_id`);
+
+INSERT INTO `sessions`(`id`, `user_id`, `created_at`, `last_used_at`)
+    SELECT `family_id`, `user_id`, MIN(`issued_at`), MAX(`issued_at`)
+    FROM `refresh_tokens`
+    GROUP BY `family_id`, `user_id`;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS `totp_recovery_codes_user`;
+DROP TABLE IF EXISTS `totp_recovery_codes`;
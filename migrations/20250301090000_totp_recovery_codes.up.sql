@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS `totp_recovery_codes`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    -- argon2 PHC string
+    `code_hash` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    `used_at` DATETIME NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `totp_recovery_codes_user` ON `totp_recovery_codes`(`user_id`);
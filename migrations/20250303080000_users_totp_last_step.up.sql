@@ -0,0 +1,2 @@
+-- last accepted totp time step, a code can't be used twice
+ALTER TABLE `users` ADD COLUMN `totp_last_step` INTEGER NULL;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS `password_reset_tokens`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    -- sha256 of the emailed token
+    `token_hash` BLOB NOT NULL UNIQUE,
+    `created_at` DATETIME NOT NULL,
+    `expires_at` DATETIME NOT NULL,
+    `used_at` DATETIME NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `password_reset_tokens_user` ON `password_reset_tokens`(`user_id`);
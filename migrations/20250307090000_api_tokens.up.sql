@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS `api_tokens`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    `name` TEXT NOT NULL,
+    -- sha256 of the token
+    `token_hash` BLOB NOT NULL UNIQUE,
+    -- `|` separated, like users.roles
+    `scopes` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    `expires_at` DATETIME NOT NULL,
+    `last_used_at` DATETIME NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `api_tokens_user` ON `api_tokens`(`user_id`);
@@ -0,0 +1,5 @@
+-- set once the user validated an authenticator, the signup secret is never shown
+ALTER TABLE `users` ADD COLUMN `totp_enrolled` BOOLEAN NOT NULL DEFAULT 0;
+UPDATE `users` SET `totp_enrolled`=1
+WHERE `totp_last_step` IS NOT NULL
+    OR `id` IN (SELECT `user_id` FROM `totp_recovery_codes`);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS `webauthn_credentials`(
+    `id` BLOB PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    -- raw credential id, the authenticator refers to it
+    `credential_id` BLOB NOT NULL UNIQUE,
+    `name` TEXT NOT NULL,
+    -- serialized webauthn_rs Passkey: public key and signature counter
+    `passkey` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    `last_used_at` DATETIME NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `webauthn_credentials_user` ON `webauthn_credentials`(`user_id`);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS `oidc_identities_user`;
+DROP TABLE IF EXISTS `oidc_identities`;
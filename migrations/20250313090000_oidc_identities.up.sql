@@ -0,0 +1,10 @@
+-- accounts of an OpenID Connect provider linked to a user
+CREATE TABLE IF NOT EXISTS `oidc_identities`(
+    `issuer` TEXT NOT NULL,
+    `subject` TEXT NOT NULL,
+    `user_id` BLOB NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    PRIMARY KEY(`issuer`, `subject`),
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `oidc_identities_user` ON `oidc_identities`(`user_id`);
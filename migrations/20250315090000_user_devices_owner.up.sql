@@ -0,0 +1,4 @@
+-- the user who added the device, the other rows are shares
+ALTER TABLE `user_devices` ADD COLUMN `owner` BOOLEAN NOT NULL DEFAULT 0;
+UPDATE `user_devices` SET `owner`=1
+WHERE `rowid` IN (SELECT MIN(`rowid`) FROM `user_devices` GROUP BY `device_id`);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS `ldap_identities_user`;
+DROP TABLE IF EXISTS `ldap_identities`;
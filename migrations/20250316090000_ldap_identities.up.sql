@@ -0,0 +1,9 @@
+-- directory entries linked to a user, by dn
+CREATE TABLE IF NOT EXISTS `ldap_identities`(
+    -- lowercase, dns compare case-insensitively
+    `dn` TEXT PRIMARY KEY NOT NULL,
+    `user_id` BLOB NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    FOREIGN KEY(`user_id`) REFERENCES users(`id`) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS `ldap_identities_user` ON `ldap_identities`(`user_id`);
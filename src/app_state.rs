@@ -1,11 +1,87 @@
+use crate::{
+    auth::{
+        jwt_keys::JwtKeys, login_throttle::LoginThrottle, oidc::OidcClient,
+        password::CredentialsBackend, webauthn::WebauthnState,
+    },
+    configuration::{PasswordResetSettings, ProberSettings, TotpSettings, WolSettings},
+    mail::Mailer,
+    model::device_event::DeviceEvent,
+    wol::secure_on::SecureOnCipher,
+};
 use sqlx::SqlitePool;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 
 pub type SharedAppState = Arc<AppState>;
 
 pub struct AppState {
     pub db_pool: SqlitePool,
-    pub auth_secret: String,
+    pub jwt_keys: JwtKeys,
     pub base_url: String,
     pub app_name: String,
+    pub totp: TotpSettings,
+    pub wol: WolSettings,
+    pub secure_on_cipher: SecureOnCipher,
+    pub prober: ProberSettings,
+    pub mailer: Mailer,
+    pub password_reset: PasswordResetSettings,
+    pub login_throttle: LoginThrottle,
+    /// Checks login passwords, locally or against a directory.
+    pub credentials_backend: Box<dyn CredentialsBackend>,
+    pub webauthn: WebauthnState,
+    /// `None` when OpenID Connect login isn't configured.
+    pub oidc: Option<OidcClient>,
+    /// Device state changes, published by the prober and the power on route.
+    pub device_events: broadcast::Sender<DeviceEvent>,
+}
+
+impl AppState {
+    /// Publish a device event, it's fine if nobody is listening.
+    pub fn publish(&self, event: DeviceEvent) {
+        let _ = self.device_events.send(event);
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// Settings of `configuration/` on an empty in-memory database, for handler tests.
+    pub async fn for_tests() -> SharedAppState {
+        use crate::{
+            auth::password::LocalBackend, configuration::load_settings, migration::db_migration,
+        };
+        use sqlx::sqlite::SqlitePoolOptions;
+
+        let settings =
+            load_settings(std::path::Path::new("configuration")).expect("can't load settings");
+        // every connection to `:memory:` is a new database
+        let db_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("can't open database");
+        db_migration(&db_pool).await.expect("can't run migrations");
+        Arc::new(Self {
+            db_pool,
+            jwt_keys: JwtKeys::from_settings(None, &settings.application.auth_secret)
+                .expect("can't load jwt keys"),
+            base_url: settings.application.base_url,
+            app_name: settings.application.app_name.clone(),
+            totp: settings.totp,
+            secure_on_cipher: SecureOnCipher::from_secret(&settings.wol.secure_on_secret)
+                .expect("can't derive SecureOn key"),
+            wol: settings.wol,
+            prober: settings.prober,
+            mailer: Mailer::from_settings(&settings.mail).expect("can't set up mailer"),
+            password_reset: settings.password_reset,
+            login_throttle: LoginThrottle::new(settings.login_throttle),
+            credentials_backend: Box::new(LocalBackend),
+            webauthn: WebauthnState::from_settings(
+                &settings.webauthn,
+                &settings.application.app_name,
+            )
+            .expect("can't set up webauthn"),
+            oidc: None,
+            device_events: broadcast::channel(16).0,
+        })
+    }
 }
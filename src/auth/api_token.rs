@@ -0,0 +1,75 @@
+use super::{ctx::Ctx, opaque_token};
+use crate::model::{api_token::ApiScope, role::Role};
+use axum::http::Method;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Tells API tokens apart from jwts in the `Authorization: Bearer` header.
+pub const API_TOKEN_PREFIX: &str = "wol_";
+
+/// A new token, the only time it's available in plain.
+pub fn generate() -> String {
+    format!("{}{}", API_TOKEN_PREFIX, opaque_token::generate())
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// Scope an API token needs for a route, `None` when API tokens can't use the route at all.
+///
+/// `path` is the route as declared in the router, e.g. `/api/devices/{id}`.
+pub fn required_scope(method: &Method, path: &str) -> Option<ApiScope> {
+    match (method, path) {
+        (
+            &Method::GET,
+            "/api/devices"
+            | "/api/devices/events"
+            | "/api/devices/{id}"
+            | "/api/devices/{id}/refresh",
+        ) => Some(ApiScope::DevicesRead),
+        (&Method::POST, "/api/devices/{id}/power_on") => Some(ApiScope::DevicesWake),
+        _ => None,
+    }
+}
+
+/// Ctx of an unexpired API token, `None` when the token is unknown.
+///
+/// The ctx is totp validated: the token could only be created by a totp validated session.
+#[tracing::instrument(name = "authenticate_api_token", skip_all)]
+pub async fn authenticate(pool: &SqlitePool, token: &str) -> Result<Option<Ctx>, sqlx::Error> {
+    let token_hash = opaque_token::hash(token);
+    let now = Utc::now();
+    let Some(row) = sqlx::query!(
+        r#"SELECT t.id as "id: Uuid", t.user_id as "user_id: Uuid", t.scopes,
+            t.created_at as "created_at: DateTime<Utc>", t.expires_at as "expires_at: DateTime<Utc>",
+            u.roles
+        FROM api_tokens t JOIN users u ON u.id = t.user_id
+        WHERE t.token_hash = $1 AND t.expires_at > $2"#,
+        token_hash,
+        now,
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    sqlx::query!(
+        "UPDATE api_tokens SET last_used_at=$1 WHERE id=$2",
+        now,
+        row.id,
+    )
+    .execute(pool)
+    .await?;
+
+    let mut ctx = Ctx::new(
+        row.user_id,
+        Role::parse_roles(&row.roles).unwrap_or_default(),
+    );
+    ctx.valid_totp = true;
+    ctx.iat = row.created_at.timestamp();
+    ctx.exp = row.expires_at.timestamp();
+    ctx.scopes = Some(ApiScope::parse_scopes(&row.scopes));
+    Ok(Some(ctx))
+}
@@ -0,0 +1,41 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Pending multi-step logins, one per key: starting a new one replaces the previous.
+///
+/// The state has to stay server side, it holds the challenge or the secrets of the flow.
+#[derive(Debug)]
+pub struct Ceremonies<K, T> {
+    ttl: Duration,
+    pending: Mutex<HashMap<K, (T, Instant)>>,
+}
+
+impl<K: Eq + Hash, T> Ceremonies<K, T> {
+    /// `ttl` is how long a started ceremony can be finished.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn insert(&self, key: K, state: T) {
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.retain(|_, (_, started)| now.duration_since(*started) < self.ttl);
+        pending.insert(key, (state, now));
+    }
+
+    /// The ceremony can only be finished once.
+    pub fn take(&self, key: &K) -> Option<T> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending
+            .remove(key)
+            .filter(|(_, started)| started.elapsed() < self.ttl)
+            .map(|(state, _)| state)
+    }
+}
@@ -1,7 +1,12 @@
-use super::error::{AuthError, CtxError};
+use super::{
+    api_token,
+    error::{AuthError, CtxError},
+    jwt_keys::JwtKeys,
+};
 use crate::{
     app_state::SharedAppState,
-    model::{role::Role, user::User},
+    middleware::mw_roles::ResolvedRoles,
+    model::{api_token::ApiScope, role::Role, user::User},
 };
 use axum::{
     extract::{FromRef, FromRequestParts, OptionalFromRequestParts},
@@ -12,16 +17,50 @@ use axum_extra::{
     TypedHeader,
 };
 use chrono::Utc;
-use jsonwebtoken::DecodingKey;
+use jsonwebtoken::errors::ErrorKind;
 use uuid::Uuid;
 
+/// What a jwt can be used for, a refresh token isn't accepted as an access token and vice versa.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    /// Sent as bearer token, short lived.
+    #[default]
+    Access,
+    /// Kept in the refresh cookie, only to get new access tokens.
+    Refresh,
+}
+
+impl TokenType {
+    /// `aud` of the tokens of this type, a token is only decoded for its own audience.
+    pub fn audience(self) -> &'static str {
+        match self {
+            Self::Access => "wol_server",
+            Self::Refresh => "wol_server/refresh",
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Ctx {
     pub user_id: Uuid,
+    /// Second factor validated, with a totp code or a passkey.
     pub valid_totp: bool,
     pub exp: i64,
     pub roles: Vec<Role>,
     pub iat: i64,
+    pub typ: TokenType,
+    /// See [`TokenType::audience`].
+    pub aud: String,
+    /// Session (refresh token family) the token belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    /// Only refresh tokens have an id, see [`crate::auth::refresh_token`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
+    /// Set for API tokens only, they're limited to these scopes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<ApiScope>>,
 }
 
 // Constructors.
@@ -37,6 +76,10 @@ impl Ctx {
         !self.user_id.is_nil()
     }
 
+    pub fn is_api_token(&self) -> bool {
+        self.scopes.is_some()
+    }
+
     pub fn is_admin(&self) -> bool {
         self.roles.contains(&Role::Admin)
     }
@@ -50,6 +93,8 @@ impl Ctx {
         // 30 days
         self.exp = (Utc::now() + chrono::Duration::days(30)).timestamp();
         self.iat = Utc::now().timestamp();
+        self.typ = TokenType::Refresh;
+        self.aud = TokenType::Refresh.audience().to_string();
         self
     }
 
@@ -57,6 +102,9 @@ impl Ctx {
         // 1 hours
         self.exp = (Utc::now() + chrono::Duration::hours(1)).timestamp();
         self.iat = Utc::now().timestamp();
+        self.typ = TokenType::Access;
+        self.aud = TokenType::Access.audience().to_string();
+        self.jti = None;
         self
     }
 
@@ -66,15 +114,22 @@ impl Ctx {
         self
     }
 
-    pub fn from_jwt(token: &str, key: &jsonwebtoken::DecodingKey) -> Result<Self, CtxError> {
-        jsonwebtoken::decode::<Ctx>(token, key, &jsonwebtoken::Validation::default())
-            .map_err(CtxError::JwtDecodeError)
-            .map(|data| data.claims)
+    /// Fails unless the token is of the `expected` type.
+    pub fn from_jwt(token: &str, keys: &JwtKeys, expected: TokenType) -> Result<Self, CtxError> {
+        let ctx: Self =
+            keys.decode(token, expected.audience())
+                .map_err(|err| match err.kind() {
+                    ErrorKind::InvalidAudience => CtxError::WrongTokenType(expected),
+                    _ => CtxError::JwtDecodeError(err),
+                })?;
+        match ctx.typ == expected {
+            true => Ok(ctx),
+            false => Err(CtxError::WrongTokenType(expected)),
+        }
     }
 
-    pub fn to_jwt(&self, key: jsonwebtoken::EncodingKey) -> Result<String, CtxError> {
-        jsonwebtoken::encode(&jsonwebtoken::Header::default(), self, &key)
-            .map_err(CtxError::JwtEncodeError)
+    pub fn to_jwt(&self, keys: &JwtKeys) -> Result<String, CtxError> {
+        keys.encode(self).map_err(CtxError::JwtEncodeError)
     }
 }
 
@@ -85,11 +140,19 @@ impl Default for Ctx {
             roles: Vec::new(),
             exp: 0,
             iat: 0,
+            typ: TokenType::Access,
+            aud: TokenType::Access.audience().to_string(),
             valid_totp: false,
+            sid: None,
+            jti: None,
+            scopes: None,
         }
     }
 }
 
+#[derive(Clone)]
+struct ResolvedApiToken(Ctx);
+
 impl<S> OptionalFromRequestParts<S> for Ctx
 where
     S: Send + Sync,
@@ -101,18 +164,39 @@ where
         parts: &mut axum::http::request::Parts,
         state: &S,
     ) -> Result<Option<Self>, Self::Rejection> {
-        if let Ok(TypedHeader(Authorization(bearer))) =
+        let Ok(TypedHeader(Authorization(bearer))) =
             parts.extract::<TypedHeader<Authorization<Bearer>>>().await
-        {
-            let token = Ctx::from_jwt(
-                bearer.token(),
-                &DecodingKey::from_secret(SharedAppState::from_ref(state).auth_secret.as_bytes()),
-            )?;
-
-            Ok(Some(token))
+        else {
+            return Ok(None);
+        };
+        let mut ctx = if api_token::is_api_token(bearer.token()) {
+            // middlewares and the handler all extract the ctx, look the token up once
+            match parts.extensions.get::<ResolvedApiToken>() {
+                Some(ResolvedApiToken(ctx)) => ctx.clone(),
+                None => {
+                    let ctx = api_token::authenticate(
+                        &SharedAppState::from_ref(state).db_pool,
+                        bearer.token(),
+                    )
+                    .await
+                    .map_err(CtxError::ApiTokenLookupError)?
+                    .ok_or(CtxError::InvalidApiToken)?;
+                    parts.extensions.insert(ResolvedApiToken(ctx.clone()));
+                    ctx
+                }
+            }
         } else {
-            Ok(None)
+            Ctx::from_jwt(
+                bearer.token(),
+                &SharedAppState::from_ref(state).jwt_keys,
+                TokenType::Access,
+            )?
+        };
+        // behind `resolve_roles` the roles come from the database, not from the token
+        if let Some(ResolvedRoles(roles)) = parts.extensions.get::<ResolvedRoles>() {
+            ctx.roles = roles.clone();
         }
+        Ok(Some(ctx))
     }
 }
 
@@ -129,7 +213,35 @@ where
     ) -> Result<Self, Self::Rejection> {
         match <Ctx as OptionalFromRequestParts<S>>::from_request_parts(parts, state).await {
             Ok(res) => res.ok_or(AuthError::MissingCredentials),
+            Err(CtxError::ApiTokenLookupError(err)) => Err(AuthError::UnexpectedError(
+                anyhow::Error::new(err).context("can't look up api token"),
+            )),
             Err(err) => Err(AuthError::from(err)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_only_pass_as_their_own_type() {
+        let keys = JwtKeys::from_settings(None, "secret").unwrap();
+        let mut ctx = Ctx::new(Uuid::now_v7(), vec![Role::User]);
+        let access = ctx.as_auth().to_jwt(&keys).unwrap();
+        ctx.jti = Some(Uuid::now_v7());
+        let refresh = ctx.as_refresh().to_jwt(&keys).unwrap();
+
+        assert!(Ctx::from_jwt(&access, &keys, TokenType::Access).is_ok());
+        assert!(Ctx::from_jwt(&refresh, &keys, TokenType::Refresh).is_ok());
+        assert!(matches!(
+            Ctx::from_jwt(&refresh, &keys, TokenType::Access),
+            Err(CtxError::WrongTokenType(TokenType::Access))
+        ));
+        assert!(matches!(
+            Ctx::from_jwt(&access, &keys, TokenType::Refresh),
+            Err(CtxError::WrongTokenType(TokenType::Refresh))
+        ));
+    }
+}
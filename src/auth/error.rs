@@ -1,4 +1,11 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use super::ctx::TokenType;
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use std::time::Duration;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -8,6 +15,12 @@ pub enum AuthError {
     InvalidTotp,
     #[error("Invalid totp")]
     InactiveUser,
+    #[error("Password reset required.")]
+    PasswordResetRequired,
+    #[error("The api token doesn't have the scope for this request.")]
+    InsufficientScope,
+    #[error("Too many failed attempts, retry in {} seconds.", .0.as_secs().max(1))]
+    TooManyAttempts(Duration),
     #[error("Invalid credentials.")]
     InvalidCredentials(#[source] anyhow::Error),
     #[error("Invalid jwt.")]
@@ -25,6 +38,18 @@ impl IntoResponse for AuthError {
             AuthError::MissingCredentials | AuthError::InvalidTotp | AuthError::InactiveUser => {
                 StatusCode::FORBIDDEN.into_response()
             }
+            AuthError::PasswordResetRequired | AuthError::InsufficientScope => {
+                (StatusCode::FORBIDDEN, self.to_string()).into_response()
+            }
+            AuthError::TooManyAttempts(retry_after) => {
+                let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                    Json(json!({"error": "too_many_attempts", "retry_after": retry_after_secs})),
+                )
+                    .into_response()
+            }
             AuthError::UnexpectedError(error) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("something went wrong {}", error),
@@ -40,10 +65,19 @@ pub enum CtxError {
     JwtDecodeError(#[source] jsonwebtoken::errors::Error),
     #[error("Can't encode credentials into jwt: {0}")]
     JwtEncodeError(#[source] jsonwebtoken::errors::Error),
+    #[error("Wrong token type, {0:?} expected.")]
+    WrongTokenType(TokenType),
+    #[error("Invalid or expired api token.")]
+    InvalidApiToken,
+    #[error("Can't look up api token: {0}")]
+    ApiTokenLookupError(#[source] sqlx::Error),
 }
 
 impl IntoResponse for CtxError {
     fn into_response(self) -> axum::response::Response {
+        if let CtxError::InvalidApiToken | CtxError::WrongTokenType(_) = self {
+            return (StatusCode::UNAUTHORIZED, self.to_string()).into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("error with jwt: {}", self),
@@ -51,3 +85,49 @@ impl IntoResponse for CtxError {
             .into_response()
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum OidcError {
+    #[error("OpenID Connect login is disabled.")]
+    Disabled,
+    #[error("Unknown or expired login, start again.")]
+    InvalidState,
+    #[error("The provider refused the login: {0}")]
+    Denied(String),
+    #[error("Can't talk to the provider: {0:#}")]
+    ProviderError(anyhow::Error),
+    #[error("Invalid id token: {0}")]
+    InvalidIdToken(#[from] jsonwebtoken::errors::Error),
+    #[error("The provider didn't give an email address.")]
+    MissingEmail,
+    #[error("An account already uses this email, log in with your password.")]
+    EmailTaken,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for OidcError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            OidcError::Disabled => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            OidcError::InvalidState | OidcError::MissingEmail => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            OidcError::Denied(_) | OidcError::InvalidIdToken(_) => {
+                (StatusCode::UNAUTHORIZED, self.to_string()).into_response()
+            }
+            OidcError::EmailTaken => (StatusCode::CONFLICT, self.to_string()).into_response(),
+            OidcError::AuthError(auth_error) => auth_error.into_response(),
+            OidcError::ProviderError(_) => {
+                (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+            }
+            OidcError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use super::{
+    ctx::Ctx,
+    error::AuthError,
+    opaque_token,
+    password::{hash_password, Credentials, CredentialsBackend, LocalBackend},
+};
+use crate::{
+    configuration::LdapSettings,
+    model::{role::Role, user::User},
+};
+use anyhow::Context;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use rand::Rng as _;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Result code of a bind with a wrong password.
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
+
+/// User entry of a login email.
+struct DirectoryUser {
+    dn: String,
+    email: Option<String>,
+    username: Option<String>,
+    full_name: Option<String>,
+    groups: Vec<String>,
+}
+
+/// Simple bind against a directory, see [`LdapSettings`].
+///
+/// The directory owns the roles: they're mapped from the groups of the user on every login.
+/// A user logging in for the first time gets an active local account, linked by dn.
+pub struct LdapBackend {
+    settings: LdapSettings,
+    group_roles: Vec<(String, Vec<Role>)>,
+    default_roles: Vec<Role>,
+}
+
+fn parse_roles(roles: &[String]) -> Result<Vec<Role>, anyhow::Error> {
+    roles
+        .iter()
+        .map(|role| Role::try_from(role.as_str()).map_err(|e| anyhow::anyhow!("{}: {}", e, role)))
+        .collect()
+}
+
+/// Values of an attribute, the directory decides the case of attribute names.
+fn attribute<'a>(entry: &'a SearchEntry, name: &str) -> &'a [String] {
+    entry
+        .attrs
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, values)| values.as_slice())
+        .unwrap_or_default()
+}
+
+impl LdapBackend {
+    pub fn new(settings: LdapSettings) -> Result<Self, anyhow::Error> {
+        if !settings.user_filter.contains("{email}") {
+            anyhow::bail!("the ldap user filter must contain {{email}}");
+        }
+        let group_roles = settings
+            .group_roles
+            .iter()
+            .map(|mapping| Ok((mapping.group.clone(), parse_roles(&mapping.roles)?)))
+            .collect::<Result<_, anyhow::Error>>()?;
+        let default_roles = parse_roles(&settings.default_roles)?;
+        Ok(Self {
+            settings,
+            group_roles,
+            default_roles,
+        })
+    }
+
+    async fn connect(&self) -> Result<Ldap, anyhow::Error> {
+        let conn_settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(self.settings.timeout_secs))
+            .set_starttls(self.settings.starttls);
+        let (conn, ldap) = LdapConnAsync::with_settings(conn_settings, &self.settings.url)
+            .await
+            .context("can't connect to directory")?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    /// `None` when no entry or several entries match the filter.
+    async fn find_user(
+        &self,
+        ldap: &mut Ldap,
+        email: &str,
+    ) -> Result<Option<DirectoryUser>, anyhow::Error> {
+        if let Some(bind_dn) = &self.settings.bind_dn {
+            ldap.simple_bind(
+                bind_dn,
+                self.settings.bind_password.as_deref().unwrap_or_default(),
+            )
+            .await
+            .and_then(|result| result.success())
+            .context("can't bind service account")?;
+        }
+        let filter = self.user_filter(email);
+        let (mut entries, _) = ldap
+            .search(
+                &self.settings.user_base_dn,
+                Scope::Subtree,
+                &filter,
+                vec!["mail", "uid", "cn", &self.settings.group_attribute],
+            )
+            .await
+            .and_then(|result| result.success())
+            .context("can't search user")?;
+        if entries.len() > 1 {
+            tracing::warn!("{} directory entries match {}", entries.len(), filter);
+            return Ok(None);
+        }
+        let Some(entry) = entries.pop() else {
+            return Ok(None);
+        };
+        let entry = SearchEntry::construct(entry);
+        Ok(Some(DirectoryUser {
+            email: attribute(&entry, "mail").first().cloned(),
+            username: attribute(&entry, "uid").first().cloned(),
+            full_name: attribute(&entry, "cn").first().cloned(),
+            groups: attribute(&entry, &self.settings.group_attribute).to_vec(),
+            dn: entry.dn,
+        }))
+    }
+
+    /// `user_filter` with the escaped `email`, a login email can't widen the search.
+    fn user_filter(&self, email: &str) -> String {
+        self.settings
+            .user_filter
+            .replace("{email}", &ldap_escape(email))
+    }
+
+    /// Entry of `credentials` when the password binds, `None` when the directory doesn't know the email.
+    async fn bind_user(
+        &self,
+        credentials: &Credentials,
+    ) -> Result<Option<DirectoryUser>, AuthError> {
+        let mut ldap = self.connect().await?;
+        let result = async {
+            let Some(user) = self.find_user(&mut ldap, &credentials.email).await? else {
+                return Ok(None);
+            };
+            let bind = ldap
+                .simple_bind(&user.dn, &credentials.password)
+                .await
+                .context("can't bind user")?;
+            if bind.rc == LDAP_INVALID_CREDENTIALS {
+                return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                    "Invalid password."
+                )));
+            }
+            bind.success().context("can't bind user")?;
+            Ok(Some(user))
+        }
+        .await;
+        let _ = ldap.unbind().await;
+        result
+    }
+
+    /// Union of the roles of the groups, `default_roles` for a user in none of them.
+    fn roles_for(&self, groups: &[String]) -> Vec<Role> {
+        let mut roles = Vec::new();
+        for (group, group_roles) in &self.group_roles {
+            if groups.iter().any(|g| g.eq_ignore_ascii_case(group)) {
+                for role in group_roles {
+                    if !roles.contains(role) {
+                        roles.push(role.clone());
+                    }
+                }
+            }
+        }
+        match roles.is_empty() {
+            true => self.default_roles.clone(),
+            false => roles,
+        }
+    }
+
+    #[tracing::instrument(name = "Validate ldap credentials", skip_all)]
+    async fn validate_ldap_credentials(
+        &self,
+        credentials: Credentials,
+        pool: &SqlitePool,
+    ) -> Result<Ctx, AuthError> {
+        // an empty password is an anonymous bind, which succeeds
+        if credentials.password.is_empty() {
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "Empty password."
+            )));
+        }
+        let user = tokio::time::timeout(
+            Duration::from_secs(self.settings.timeout_secs),
+            self.bind_user(&credentials),
+        )
+        .await
+        .context("directory timed out")??;
+        let Some(user) = user else {
+            if self.settings.local_fallback {
+                return LocalBackend.validate(credentials, pool).await;
+            }
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "Unknown username."
+            )));
+        };
+        let roles = self.roles_for(&user.groups);
+        if roles.is_empty() {
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "{} isn't in a group with access.",
+                user.dn
+            )));
+        }
+        let email = user.email.as_deref().unwrap_or(&credentials.email);
+        let user_id = sync_user(pool, &user, email, &roles, self.settings.link_by_email).await?;
+        Ok(Ctx::new(user_id, roles))
+    }
+}
+
+impl CredentialsBackend for LdapBackend {
+    fn validate<'a>(
+        &'a self,
+        credentials: Credentials,
+        pool: &'a SqlitePool,
+    ) -> BoxFuture<'a, Result<Ctx, AuthError>> {
+        Box::pin(self.validate_ldap_credentials(credentials, pool))
+    }
+}
+
+/// Local account of a directory user, created on the first login and kept to the directory roles.
+///
+/// The entry is linked to the account by dn. A local account with the same email is only taken
+/// over with `link_by_email`: else whoever can set an email in the directory could.
+async fn sync_user(
+    pool: &SqlitePool,
+    user: &DirectoryUser,
+    email: &str,
+    roles: &[Role],
+    link_by_email: bool,
+) -> Result<Uuid, AuthError> {
+    let roles = roles
+        .iter()
+        .cloned()
+        .map(<&str>::from)
+        .collect::<Vec<_>>()
+        .join("|");
+    let dn = user.dn.to_lowercase();
+    let mut transaction = pool.begin().await.context("can't start transaction")?;
+    let linked = sqlx::query_scalar!(
+        r#"SELECT user_id as "user_id: Uuid" FROM ldap_identities WHERE dn = $1"#,
+        dn
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("can't fetch directory identity")?;
+    let user_id = match linked {
+        Some(user_id) => user_id,
+        None => {
+            let existing = sqlx::query_scalar!(
+                r#"SELECT id as "id: Uuid" FROM users WHERE email = $1"#,
+                email
+            )
+            .fetch_optional(&mut *transaction)
+            .await
+            .context("can't fetch user by email")?;
+            let user_id = match existing {
+                Some(user_id) if link_by_email => {
+                    tracing::info!("linking {} to user {}", user.dn, user_id);
+                    user_id
+                }
+                Some(user_id) => {
+                    return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                        "{} has the email of user {}, which isn't linked to it",
+                        user.dn,
+                        user_id
+                    )));
+                }
+                None => create_user(&mut transaction, user, email, &roles).await?,
+            };
+            let created_at = Utc::now();
+            sqlx::query!(
+                r#"INSERT INTO ldap_identities(dn, user_id, created_at) VALUES ($1, $2, $3)"#,
+                dn,
+                user_id,
+                created_at,
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("can't link directory identity")?;
+            user_id
+        }
+    };
+    sqlx::query!(
+        "UPDATE users SET roles=$1, update_date=datetime('now','localtime') WHERE id=$2",
+        roles,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't update roles")?;
+    transaction
+        .commit()
+        .await
+        .context("can't commit transaction")?;
+    Ok(user_id)
+}
+
+async fn create_user(
+    transaction: &mut Transaction<'_, Sqlite>,
+    user: &DirectoryUser,
+    email: &str,
+    roles: &str,
+) -> Result<Uuid, AuthError> {
+    let base_username = user
+        .username
+        .clone()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email).to_string());
+    let username = User::available_username(transaction, &base_username)
+        .await
+        .context("can't check username")?;
+    // nobody knows it, the account logs in through the directory
+    let password = hash_password(&opaque_token::generate()).await?;
+    let totp_secret = rand::thread_rng().gen::<[u8; 21]>().to_vec();
+    let full_name = user.full_name.clone().unwrap_or_else(|| username.clone());
+    let user_id = Uuid::now_v7();
+    sqlx::query!(
+        r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret,
+            active, join_date)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 1, datetime('now','localtime'))"#,
+        user_id,
+        roles,
+        username,
+        password,
+        email,
+        full_name,
+        totp_secret,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("can't create user")?;
+    tracing::info!("created user {} for {}", user_id, user.dn);
+    Ok(user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::AppState, configuration::LdapGroupRoles};
+
+    fn backend() -> LdapBackend {
+        LdapBackend::new(LdapSettings {
+            url: "ldap://localhost".to_string(),
+            starttls: false,
+            timeout_secs: 5,
+            bind_dn: None,
+            bind_password: None,
+            user_base_dn: "ou=people,dc=example,dc=com".to_string(),
+            user_filter: "(&(objectClass=person)(mail={email}))".to_string(),
+            group_attribute: "memberOf".to_string(),
+            group_roles: vec![
+                LdapGroupRoles {
+                    group: "cn=admins,dc=example,dc=com".to_string(),
+                    roles: vec!["admin".to_string(), "user".to_string()],
+                },
+                LdapGroupRoles {
+                    group: "cn=staff,dc=example,dc=com".to_string(),
+                    roles: vec!["user".to_string()],
+                },
+            ],
+            default_roles: vec![],
+            local_fallback: false,
+            link_by_email: false,
+        })
+        .unwrap()
+    }
+
+    fn directory_user(dn: &str) -> DirectoryUser {
+        DirectoryUser {
+            dn: dn.to_string(),
+            email: None,
+            username: Some("alice".to_string()),
+            full_name: None,
+            groups: vec![],
+        }
+    }
+
+    #[test]
+    fn roles_are_the_union_of_the_groups() {
+        let backend = backend();
+        let roles = backend.roles_for(&[
+            "CN=Staff,DC=example,DC=com".to_string(),
+            "cn=admins,dc=example,dc=com".to_string(),
+            "cn=other,dc=example,dc=com".to_string(),
+        ]);
+        assert_eq!(roles, vec![Role::Admin, Role::User]);
+    }
+
+    #[test]
+    fn users_in_no_group_get_the_default_roles() {
+        let mut backend = backend();
+        assert!(backend.roles_for(&[]).is_empty());
+        backend.default_roles = vec![Role::User];
+        assert_eq!(
+            backend.roles_for(&["cn=other,dc=example,dc=com".to_string()]),
+            vec![Role::User]
+        );
+    }
+
+    #[test]
+    fn the_login_email_is_escaped_in_the_filter() {
+        assert_eq!(
+            backend().user_filter("*)(uid=*\\\0"),
+            "(&(objectClass=person)(mail=\\2a\\29\\28uid=\\2a\\5c\\00))"
+        );
+    }
+
+    #[tokio::test]
+    async fn directory_users_are_linked_by_dn() {
+        let state = AppState::for_tests().await;
+        let pool = &state.db_pool;
+        let user = directory_user("uid=alice,ou=people,dc=example,dc=com");
+        let user_id = sync_user(pool, &user, "alice@example.com", &[Role::User], false)
+            .await
+            .unwrap();
+        // same entry with another email and new roles
+        let user = directory_user("UID=Alice,ou=people,dc=example,dc=com");
+        let again = sync_user(pool, &user, "alice2@example.com", &[Role::Admin], false)
+            .await
+            .unwrap();
+        assert_eq!(again, user_id);
+        let roles = sqlx::query_scalar!("SELECT roles FROM users WHERE id = $1", user_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(roles, "admin");
+    }
+
+    #[tokio::test]
+    async fn local_accounts_are_only_linked_when_configured() {
+        let state = AppState::for_tests().await;
+        let pool = &state.db_pool;
+        let alice = sync_user(
+            pool,
+            &directory_user("uid=alice,dc=example,dc=com"),
+            "alice@example.com",
+            &[Role::Admin],
+            false,
+        )
+        .await
+        .unwrap();
+        let mallory = directory_user("uid=mallory,dc=example,dc=com");
+        let refused = sync_user(pool, &mallory, "alice@example.com", &[Role::User], false).await;
+        assert!(matches!(refused, Err(AuthError::InvalidCredentials(_))));
+        let linked = sync_user(pool, &mallory, "alice@example.com", &[Role::User], true)
+            .await
+            .unwrap();
+        assert_eq!(linked, alice);
+    }
+}
@@ -0,0 +1,227 @@
+use super::error::AuthError;
+use crate::configuration::LoginThrottleSettings;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How often forgotten failures are dropped from memory.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ThrottleKey {
+    /// Normalized email, unknown accounts are throttled the same way as existing ones.
+    Account(String),
+    Ip(IpAddr),
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Attempts {
+    failures: HashMap<ThrottleKey, Failures>,
+    pruned: Instant,
+}
+
+/// In-process count of failed logins, per account and per client ip.
+///
+/// Past the threshold of a key, each failure locks it out for twice as long as the previous one.
+#[derive(Debug)]
+pub struct LoginThrottle {
+    settings: LoginThrottleSettings,
+    attempts: Mutex<Attempts>,
+}
+
+impl LoginThrottle {
+    pub fn new(settings: LoginThrottleSettings) -> Self {
+        Self {
+            settings,
+            attempts: Mutex::new(Attempts {
+                failures: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    fn keys(&self, email: &str, ip: Option<IpAddr>) -> Vec<(ThrottleKey, u32)> {
+        let mut keys = vec![(
+            ThrottleKey::Account(email.trim().to_lowercase()),
+            self.settings.account_threshold,
+        )];
+        if let Some(ip) = ip {
+            keys.push((ThrottleKey::Ip(ip), self.settings.ip_threshold));
+        }
+        keys
+    }
+
+    fn lockout(&self, count: u32, threshold: u32) -> Duration {
+        let doublings = (count - threshold).min(32);
+        Duration::from_secs(
+            self.settings
+                .base_lockout_secs
+                .saturating_mul(1 << doublings)
+                .min(self.settings.max_lockout_secs),
+        )
+    }
+
+    /// Fails with [`AuthError::TooManyAttempts`] while the account or the ip is locked out.
+    pub fn check(&self, email: &str, ip: Option<IpAddr>) -> Result<(), AuthError> {
+        self.check_at(email, ip, Instant::now())
+    }
+
+    fn check_at(&self, email: &str, ip: Option<IpAddr>, now: Instant) -> Result<(), AuthError> {
+        let attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let retry_after = self
+            .keys(email, ip)
+            .into_iter()
+            .filter_map(|(key, _)| attempts.failures.get(&key)?.locked_until)
+            .filter_map(|locked_until| locked_until.checked_duration_since(now))
+            .max();
+        match retry_after {
+            Some(retry_after) if !retry_after.is_zero() => {
+                Err(AuthError::TooManyAttempts(retry_after))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, email: &str, ip: Option<IpAddr>) {
+        self.record_failure_at(email, ip, Instant::now())
+    }
+
+    fn record_failure_at(&self, email: &str, ip: Option<IpAddr>, now: Instant) {
+        let forget_after = Duration::from_secs(self.settings.forget_after_mins * 60);
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(attempts.pruned) > PRUNE_INTERVAL {
+            attempts.failures.retain(|_, failures| {
+                now.duration_since(failures.last_failure) < forget_after
+                    || failures.locked_until.is_some_and(|until| until > now)
+            });
+            attempts.pruned = now;
+        }
+
+        for (key, threshold) in self.keys(email, ip) {
+            let failures = attempts.failures.entry(key.clone()).or_insert(Failures {
+                count: 0,
+                last_failure: now,
+                locked_until: None,
+            });
+            if now.duration_since(failures.last_failure) >= forget_after {
+                failures.count = 0;
+            }
+            failures.count += 1;
+            failures.last_failure = now;
+            if failures.count >= threshold {
+                let lockout = self.lockout(failures.count, threshold);
+                failures.locked_until = Some(now + lockout);
+                tracing::warn!("login locked out for {:?}s: {:?}", lockout.as_secs(), key);
+            }
+        }
+    }
+
+    /// A successful login clears the failures of the account, not those of the ip: else logging
+    /// into an own account between guesses would reset the ip lockout.
+    pub fn record_success(&self, email: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        attempts
+            .failures
+            .remove(&ThrottleKey::Account(email.trim().to_lowercase()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = "bob@example.com";
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(LoginThrottleSettings {
+            account_threshold: 3,
+            ip_threshold: 5,
+            base_lockout_secs: 30,
+            max_lockout_secs: 120,
+            forget_after_mins: 60,
+        })
+    }
+
+    fn retry_after(throttle: &LoginThrottle, ip: Option<IpAddr>, now: Instant) -> Option<u64> {
+        match throttle.check_at(EMAIL, ip, now) {
+            Ok(()) => None,
+            Err(AuthError::TooManyAttempts(retry_after)) => Some(retry_after.as_secs()),
+            Err(e) => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn accounts_are_locked_out_at_the_threshold() {
+        let throttle = throttle();
+        let now = Instant::now();
+        for _ in 0..2 {
+            throttle.record_failure_at(EMAIL, None, now);
+        }
+        assert_eq!(retry_after(&throttle, None, now), None);
+        throttle.record_failure_at(" Bob@Example.com", None, now);
+        assert_eq!(retry_after(&throttle, None, now), Some(30));
+        assert_eq!(
+            retry_after(&throttle, None, now + Duration::from_secs(30)),
+            None
+        );
+    }
+
+    #[test]
+    fn lockouts_double_up_to_the_cap() {
+        let throttle = throttle();
+        let now = Instant::now();
+        let lockouts = (0..6)
+            .map(|_| {
+                throttle.record_failure_at(EMAIL, None, now);
+                retry_after(&throttle, None, now)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lockouts,
+            [None, None, Some(30), Some(60), Some(120), Some(120)]
+        );
+        // no overflow far past the threshold
+        assert_eq!(throttle.lockout(200, 3), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn old_failures_are_forgotten() {
+        let throttle = throttle();
+        let now = Instant::now();
+        for _ in 0..2 {
+            throttle.record_failure_at(EMAIL, None, now);
+        }
+        let later = now + Duration::from_secs(60 * 60);
+        throttle.record_failure_at(EMAIL, None, later);
+        throttle.record_failure_at(EMAIL, None, later);
+        assert_eq!(retry_after(&throttle, None, later), None);
+        throttle.record_failure_at(EMAIL, None, later);
+        assert_eq!(retry_after(&throttle, None, later), Some(30));
+    }
+
+    #[test]
+    fn a_success_clears_the_account_but_not_the_ip() {
+        let throttle = throttle();
+        let now = Instant::now();
+        let ip = Some(IpAddr::from([192, 0, 2, 1]));
+        for i in 0..5 {
+            throttle.record_failure_at(&format!("user{i}@example.com"), ip, now);
+        }
+        for _ in 0..3 {
+            throttle.record_failure_at(EMAIL, None, now);
+        }
+        throttle.record_success(EMAIL);
+        assert_eq!(retry_after(&throttle, None, now), None);
+        assert_eq!(retry_after(&throttle, ip, now), Some(30));
+    }
+}
@@ -1,8 +1,24 @@
-use super::REFRESH_COOKIE;
+use super::{
+    ctx::{Ctx, TokenType},
+    refresh_token, REFRESH_COOKIE,
+};
+use crate::app_state::AppState;
+use anyhow::Context;
 use tower_cookies::cookie::time::Duration;
 use tower_cookies::{Cookie, Cookies};
 
-pub async fn logout(cookies: Cookies) -> anyhow::Result<()> {
+/// Revoke the session of the refresh cookie, then clear it.
+pub async fn logout(state: &AppState, cookies: Cookies) -> anyhow::Result<()> {
+    let session_id = cookies.get(REFRESH_COOKIE).and_then(|cookie| {
+        Ctx::from_jwt(cookie.value(), &state.jwt_keys, TokenType::Refresh)
+            .ok()?
+            .sid
+    });
+    if let Some(session_id) = session_id {
+        refresh_token::revoke_session(&state.db_pool, session_id)
+            .await
+            .context("can't revoke session")?;
+    }
     let refresh_cookie = Cookie::build((REFRESH_COOKIE, ""))
         .max_age(Duration::seconds(0))
         .http_only(true)
@@ -1,7 +1,19 @@
+pub mod api_token;
+pub mod ceremonies;
 pub mod ctx;
 pub mod error;
+pub mod jwt_keys;
+pub mod ldap;
+pub mod login_throttle;
 pub mod logout;
+pub mod oidc;
+pub mod opaque_token;
 pub mod password;
+pub mod password_reset;
+pub mod recovery_codes;
+pub mod refresh_token;
+pub mod totp;
+pub mod webauthn;
 
 pub const AUTH_HEADER: &str = "Authorization";
 pub const REFRESH_COOKIE: &str = "WOL_REFRESH_TOKEN";
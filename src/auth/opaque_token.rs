@@ -0,0 +1,17 @@
+//! Random bearer secrets only known by their holder, the server keeps a sha256 of them.
+use rand::Rng as _;
+use sha2::{Digest, Sha256};
+
+/// 32 random bytes, hex encoded.
+pub fn generate() -> String {
+    rand::thread_rng()
+        .gen::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Only the hash is stored, a leaked database doesn't give usable tokens.
+pub fn hash(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
@@ -4,9 +4,11 @@ use crate::{auth::error::AuthError, telemetry::spawn_blocking_with_tracing};
 use anyhow::Context;
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
 use argon2::{PasswordHash, PasswordVerifier};
+use futures_util::future::BoxFuture;
 use rand;
 use serde::Deserialize;
 use sqlx::SqlitePool;
+use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 pub struct Credentials {
@@ -62,8 +64,39 @@ async fn get_user_credentials(
     ))
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// Where login passwords are checked, picked from the settings at startup.
+pub trait CredentialsBackend: Send + Sync {
+    /// Ctx of the user with these credentials, [`AuthError::InvalidCredentials`] when they're wrong.
+    fn validate<'a>(
+        &'a self,
+        credentials: Credentials,
+        pool: &'a SqlitePool,
+    ) -> BoxFuture<'a, Result<Ctx, AuthError>>;
+}
+
+/// Argon2 hashes stored in `users`.
+pub struct LocalBackend;
+
+impl CredentialsBackend for LocalBackend {
+    fn validate<'a>(
+        &'a self,
+        credentials: Credentials,
+        pool: &'a SqlitePool,
+    ) -> BoxFuture<'a, Result<Ctx, AuthError>> {
+        Box::pin(validate_local_credentials(credentials, pool))
+    }
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(backend, credentials, pool))]
 pub async fn validate_credentials(
+    backend: &dyn CredentialsBackend,
+    credentials: Credentials,
+    pool: &SqlitePool,
+) -> Result<Ctx, AuthError> {
+    backend.validate(credentials, pool).await
+}
+
+async fn validate_local_credentials(
     credentials: Credentials,
     pool: &SqlitePool,
 ) -> Result<Ctx, AuthError> {
@@ -107,6 +140,18 @@ fn verify_password_hash(
         .map_err(AuthError::InvalidCredentials)
 }
 
+/// Check `password_candidate` against an argon2 PHC string without blocking the runtime.
+pub async fn verify_password(
+    expected_password_hash: String,
+    password_candidate: String,
+) -> Result<(), AuthError> {
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, password_candidate)
+    })
+    .await
+    .context("Failed to spawn blocking task.")?
+}
+
 #[tracing::instrument(name = "hash_password_sync", skip(password))]
 pub fn hash_password_sync(password: &str) -> Result<String, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
@@ -126,3 +171,23 @@ pub async fn hash_password(password: &str) -> Result<String, anyhow::Error> {
         .await?
         .context("Failed to hash password")
 }
+
+/// Store a new password for `user_id`, it also clears `force_password_reset`.
+pub async fn update_password(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    password: &str,
+) -> Result<(), anyhow::Error> {
+    let hashed_password = hash_password(password).await?;
+    sqlx::query!(
+        r#"UPDATE users
+        SET password=$1, force_password_reset=0, update_date=datetime('now','localtime')
+        WHERE id=$2"#,
+        hashed_password,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("can't update password")?;
+    Ok(())
+}
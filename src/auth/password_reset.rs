@@ -0,0 +1,95 @@
+use super::opaque_token;
+use crate::{app_state::AppState, mail::Email};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Replace the pending reset tokens of `user_id` with a new one, the plain token is only returned here.
+#[tracing::instrument(name = "create_password_reset_token", skip(pool))]
+pub async fn create_token(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    ttl: Duration,
+) -> Result<String, anyhow::Error> {
+    let token = opaque_token::generate();
+    let token_hash = opaque_token::hash(&token);
+    let id = Uuid::now_v7();
+    let created_at = Utc::now();
+    let expires_at = created_at + ttl;
+    let mut transaction = pool.begin().await.context("can't start transaction")?;
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id=$1 AND used_at IS NULL",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't delete reset tokens")?;
+    sqlx::query!(
+        r#"INSERT INTO password_reset_tokens(id, user_id, token_hash, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)"#,
+        id,
+        user_id,
+        token_hash,
+        created_at,
+        expires_at,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't store reset token")?;
+    transaction
+        .commit()
+        .await
+        .context("can't commit transaction")?;
+    Ok(token)
+}
+
+/// Use up `token`, returning the user it was issued to, `None` when it's unknown, used or expired.
+#[tracing::instrument(name = "consume_password_reset_token", skip_all)]
+pub async fn consume_token(pool: &SqlitePool, token: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    let token_hash = opaque_token::hash(token);
+    let now = Utc::now();
+    sqlx::query_scalar!(
+        r#"UPDATE password_reset_tokens SET used_at=$1
+        WHERE token_hash=$2 AND used_at IS NULL AND expires_at > $1
+        RETURNING user_id as "user_id: Uuid""#,
+        now,
+        token_hash,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Mail a reset link to `email` if it belongs to an active user, unknown addresses are ignored.
+#[tracing::instrument(name = "send_password_reset", skip_all)]
+pub async fn send_reset_mail(state: &AppState, email: &str) -> Result<(), anyhow::Error> {
+    let user = sqlx::query!(
+        r#"SELECT id as "id: Uuid", email FROM users WHERE email=$1 AND active=1"#,
+        email
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    .context("can't fetch user")?;
+    let Some(user) = user else {
+        tracing::info!("password reset requested for an unknown address");
+        return Ok(());
+    };
+    let ttl_mins = state.password_reset.token_ttl_mins;
+    let token = create_token(&state.db_pool, user.id, Duration::minutes(ttl_mins as i64)).await?;
+    state
+        .mailer
+        .send(Email {
+            to: user.email,
+            subject: format!("{} password reset", state.app_name),
+            body: format!(
+                "A password reset was requested for your account.\n\n\
+                Open {}/auth/reset_password?token={} to choose a new password, \
+                the link expires in {} minutes.\n\n\
+                If you didn't ask for it, ignore this mail.\n",
+                state.base_url, token, ttl_mins
+            ),
+        })
+        .await
+        .context("can't send reset mail")?;
+    Ok(())
+}
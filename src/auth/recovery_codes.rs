@@ -0,0 +1,122 @@
+use super::{
+    error::AuthError,
+    password::{hash_password, verify_password},
+};
+use anyhow::Context;
+use chrono::Utc;
+use rand::Rng as _;
+use sqlx::{SqliteConnection, SqlitePool};
+use uuid::Uuid;
+
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+// no 0/o, 1/l/i: codes get copied by hand
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    let code = (0..RECOVERY_CODE_LEN)
+        .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect::<String>();
+    let (head, tail) = code.split_at(RECOVERY_CODE_LEN / 2);
+    format!("{}-{}", head, tail)
+}
+
+/// Codes are stored and compared without dashes, spaces and case.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// A 6 digits totp code is never a recovery code, it spares the argon2 checks.
+pub fn looks_like_recovery_code(code: &str) -> bool {
+    normalize(code).len() == RECOVERY_CODE_LEN
+}
+
+/// Replace the recovery codes of `user_id` with a new set, the plain codes are only returned here.
+#[tracing::instrument(name = "regenerate_recovery_codes", skip(pool))]
+pub async fn regenerate(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<String>, AuthError> {
+    let codes = (0..RECOVERY_CODES)
+        .map(|_| generate_code())
+        .collect::<Vec<_>>();
+    let mut code_hashes = Vec::with_capacity(codes.len());
+    for code in &codes {
+        code_hashes.push(hash_password(&normalize(code)).await?);
+    }
+
+    let created_at = Utc::now();
+    let mut transaction = pool.begin().await.context("can't start transaction")?;
+    sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_id=$1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("can't delete recovery codes")?;
+    for code_hash in code_hashes {
+        let id = Uuid::now_v7();
+        sqlx::query!(
+            r#"INSERT INTO totp_recovery_codes(id, user_id, code_hash, created_at)
+                VALUES ($1, $2, $3, $4)"#,
+            id,
+            user_id,
+            code_hash,
+            created_at,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("can't store recovery code")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("can't commit transaction")?;
+    Ok(codes)
+}
+
+/// Drop the recovery codes of `user_id`, e.g. when it enrols a new totp secret.
+pub async fn delete_all(conn: &mut SqliteConnection, user_id: Uuid) -> Result<(), AuthError> {
+    sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_id=$1", user_id)
+        .execute(conn)
+        .await
+        .context("can't delete recovery codes")?;
+    Ok(())
+}
+
+/// Use up a recovery code of `user_id`, `false` when it's unknown or already used.
+#[tracing::instrument(name = "redeem_recovery_code", skip(pool, code))]
+pub async fn redeem(pool: &SqlitePool, user_id: Uuid, code: &str) -> Result<bool, AuthError> {
+    let stored_codes = sqlx::query!(
+        r#"SELECT id as "id: Uuid", code_hash FROM totp_recovery_codes
+        WHERE user_id=$1 AND used_at IS NULL"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("can't fetch recovery codes")?;
+    let code = normalize(code);
+    for stored_code in stored_codes {
+        if verify_password(stored_code.code_hash, code.clone())
+            .await
+            .is_err()
+        {
+            continue;
+        }
+        let used_at = Utc::now();
+        // a concurrent request may have used it meanwhile
+        let redeemed = sqlx::query!(
+            "UPDATE totp_recovery_codes SET used_at=$1 WHERE id=$2 AND used_at IS NULL",
+            used_at,
+            stored_code.id,
+        )
+        .execute(pool)
+        .await
+        .context("can't use recovery code")?
+        .rows_affected()
+            > 0;
+        if redeemed {
+            tracing::info!("recovery code used by user {}", user_id);
+        }
+        return Ok(redeemed);
+    }
+    Ok(false)
+}
@@ -0,0 +1,309 @@
+use super::{
+    ctx::{Ctx, TokenType},
+    error::AuthError,
+    REFRESH_COOKIE,
+};
+use crate::{app_state::AppState, model::role::Role};
+use anyhow::Context;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use std::{convert::Infallible, net::SocketAddr};
+use tower_cookies::cookie::{time::Duration, SameSite};
+use tower_cookies::Cookie;
+use uuid::Uuid;
+
+/// A token presented again that soon after its rotation is a concurrent refresh of the same
+/// client (e.g. two tabs), not a leak: it's refused without revoking the session.
+const REUSE_GRACE_SECS: i64 = 10;
+
+/// Client a session is issued to, helps users recognise their sessions.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(|user_agent| user_agent.chars().take(512).collect());
+        let ip_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(client)| client.ip().to_string());
+        Ok(Self {
+            user_agent,
+            ip_address,
+        })
+    }
+}
+
+pub fn refresh_cookie(refresh_jwt: String) -> Cookie<'static> {
+    Cookie::build((REFRESH_COOKIE, refresh_jwt))
+        .max_age(Duration::days(30))
+        .same_site(SameSite::Lax)
+        .path("/")
+        .http_only(true)
+        .build()
+}
+
+/// Issue a refresh token for `ctx` in its session (token family),
+/// a new session is started when `ctx.sid` is `None`.
+///
+/// The token still valid in the session, if any, is rotated out.
+/// Returns the refresh ctx, its `sid` has to end up in the auth tokens too.
+pub async fn issue(
+    state: &AppState,
+    ctx: &Ctx,
+    client: &ClientInfo,
+) -> Result<(Ctx, String), AuthError> {
+    let mut refresh_ctx = ctx.clone();
+    refresh_ctx.as_refresh();
+    let jti = Uuid::now_v7();
+    let session_id = ctx.sid.unwrap_or(jti);
+    refresh_ctx.jti = Some(jti);
+    refresh_ctx.sid = Some(session_id);
+    let refresh_jwt = refresh_ctx.to_jwt(&state.jwt_keys)?;
+
+    let issued_at = DateTime::from_timestamp(refresh_ctx.iat, 0).unwrap_or_else(Utc::now);
+    let expires_at = DateTime::from_timestamp(refresh_ctx.exp, 0).unwrap_or_else(Utc::now);
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("can't start transaction")?;
+    sqlx::query!(
+        r#"INSERT INTO sessions(id, user_id, user_agent, ip_address, created_at, last_used_at)
+            VALUES ($1, $2, $3, $4, $5, $5)
+        ON CONFLICT(id) DO UPDATE
+            SET user_agent=excluded.user_agent, ip_address=excluded.ip_address,
+                last_used_at=excluded.last_used_at"#,
+        session_id,
+        refresh_ctx.user_id,
+        client.user_agent,
+        client.ip_address,
+        issued_at,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't store session")?;
+    sqlx::query!(
+        r#"UPDATE refresh_tokens SET rotated_at=$1
+        WHERE family_id=$2 AND rotated_at IS NULL AND revoked_at IS NULL"#,
+        issued_at,
+        session_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't rotate refresh tokens")?;
+    sqlx::query!(
+        r#"INSERT INTO refresh_tokens(jti, family_id, user_id, issued_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)"#,
+        jti,
+        session_id,
+        refresh_ctx.user_id,
+        issued_at,
+        expires_at,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't store refresh token")?;
+    transaction
+        .commit()
+        .await
+        .context("can't commit transaction")?;
+    Ok((refresh_ctx, refresh_jwt))
+}
+
+/// Validate a refresh token and mark it as used, it can't be presented again.
+///
+/// Presenting an already rotated token means it leaked:
+/// the whole session is revoked, the legit client included.
+/// The roles come from the database again, an inactive user can't refresh.
+pub async fn consume(state: &AppState, refresh_jwt: &str) -> Result<Ctx, AuthError> {
+    let mut ctx = Ctx::from_jwt(refresh_jwt, &state.jwt_keys, TokenType::Refresh)?;
+    let jti = ctx
+        .jti
+        .ok_or(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "Refresh token without jti."
+        )))?;
+    let now = Utc::now();
+    let consumed = sqlx::query!(
+        r#"UPDATE refresh_tokens SET rotated_at=$1
+        WHERE jti=$2 AND rotated_at IS NULL AND revoked_at IS NULL AND expires_at > $1"#,
+        now,
+        jti,
+    )
+    .execute(&state.db_pool)
+    .await
+    .context("can't consume refresh token")?
+    .rows_affected()
+        > 0;
+    if consumed {
+        let user = sqlx::query!("SELECT roles, active FROM users WHERE id=$1", ctx.user_id)
+            .fetch_optional(&state.db_pool)
+            .await
+            .context("can't fetch user")?
+            .ok_or(AuthError::InactiveUser)?;
+        if !user.active {
+            return Err(AuthError::InactiveUser);
+        }
+        ctx.roles = Role::parse_roles(&user.roles).map_err(|e| {
+            AuthError::UnexpectedError(anyhow::anyhow!("invalid stored roles: {}", e))
+        })?;
+        return Ok(ctx);
+    }
+
+    let token = sqlx::query!(
+        r#"SELECT family_id as "family_id: Uuid", rotated_at as "rotated_at: DateTime<Utc>",
+            revoked_at as "revoked_at: DateTime<Utc>"
+        FROM refresh_tokens WHERE jti=$1"#,
+        jti,
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    .context("can't fetch refresh token")?
+    .ok_or(AuthError::InvalidCredentials(anyhow::anyhow!(
+        "Unknown refresh token."
+    )))?;
+    let grace_start = now - chrono::Duration::seconds(REUSE_GRACE_SECS);
+    match (token.revoked_at, token.rotated_at) {
+        (None, Some(rotated_at)) if rotated_at > grace_start => {
+            tracing::info!("refresh token {} presented again right after rotation", jti);
+        }
+        (None, Some(_)) => {
+            tracing::warn!(
+                "refresh token {} of user {} reused, revoking session {}",
+                jti,
+                ctx.user_id,
+                token.family_id
+            );
+            revoke_session(&state.db_pool, token.family_id)
+                .await
+                .context("can't revoke session")?;
+        }
+        _ => {}
+    }
+    Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+        "Refresh token already used or revoked."
+    )))
+}
+
+/// Revoke every refresh token of a session.
+pub async fn revoke_session(pool: &SqlitePool, session_id: Uuid) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"UPDATE refresh_tokens SET revoked_at=$1 WHERE family_id=$2 AND revoked_at IS NULL"#,
+        now,
+        session_id,
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::SharedAppState;
+
+    async fn login() -> (SharedAppState, Uuid, String) {
+        let state = AppState::for_tests().await;
+        let user_id = Uuid::now_v7();
+        sqlx::query!(
+            r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret, active)
+            VALUES ($1, 'user', 'alice', '', 'alice@example.com', 'Alice', x'00', 1)"#,
+            user_id,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        let ctx = Ctx::new(user_id, vec![Role::User]);
+        let (_, refresh_jwt) = issue(&state, &ctx, &ClientInfo::default()).await.unwrap();
+        (state, user_id, refresh_jwt)
+    }
+
+    async fn refresh(state: &AppState, refresh_jwt: &str) -> Result<(Ctx, String), AuthError> {
+        let ctx = consume(state, refresh_jwt).await?;
+        issue(state, &ctx, &ClientInfo::default()).await
+    }
+
+    /// Pretend `refresh_jwt` was rotated long ago, out of the concurrent refresh grace.
+    async fn age_rotation(state: &AppState, refresh_jwt: &str) {
+        let jti = Ctx::from_jwt(refresh_jwt, &state.jwt_keys, TokenType::Refresh)
+            .unwrap()
+            .jti;
+        let rotated_at = Utc::now() - chrono::Duration::minutes(5);
+        sqlx::query!(
+            "UPDATE refresh_tokens SET rotated_at=$1 WHERE jti=$2",
+            rotated_at,
+            jti,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn tokens_rotate_within_their_session() {
+        let (state, _, first) = login().await;
+        let (first_ctx, second) = refresh(&state, &first).await.unwrap();
+        let (second_ctx, _) = refresh(&state, &second).await.unwrap();
+        assert_eq!(first_ctx.sid, second_ctx.sid);
+        assert_ne!(first_ctx.jti, second_ctx.jti);
+    }
+
+    #[tokio::test]
+    async fn reusing_a_rotated_token_revokes_the_session() {
+        let (state, _, first) = login().await;
+        let (_, second) = refresh(&state, &first).await.unwrap();
+        age_rotation(&state, &first).await;
+
+        assert!(refresh(&state, &first).await.is_err());
+        // the legit client is signed out too
+        assert!(refresh(&state, &second).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_refreshes_keep_the_session() {
+        let (state, _, first) = login().await;
+        let (winner, loser) = tokio::join!(refresh(&state, &first), refresh(&state, &first));
+        let (_, second) = match (winner, loser) {
+            (Ok(refreshed), Err(_)) | (Err(_), Ok(refreshed)) => refreshed,
+            _ => panic!("exactly one refresh should win"),
+        };
+        assert!(refresh(&state, &second).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn refreshes_read_the_user_again() {
+        let (state, user_id, first) = login().await;
+        sqlx::query!("UPDATE users SET roles='admin|user' WHERE id=$1", user_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let (ctx, second) = refresh(&state, &first).await.unwrap();
+        assert_eq!(ctx.roles, vec![Role::Admin, Role::User]);
+
+        sqlx::query!("UPDATE users SET active=0 WHERE id=$1", user_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        assert!(matches!(
+            refresh(&state, &second).await,
+            Err(AuthError::InactiveUser)
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use super::error::AuthError;
+use crate::{app_state::AppState, configuration::TotpSettings};
+use anyhow::Context;
+use chrono::Utc;
+use totp_rs::TOTP;
+use uuid::Uuid;
+
+/// Changing it invalidates every enrolled authenticator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TotpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl From<TotpAlgorithm> for totp_rs::Algorithm {
+    fn from(algorithm: TotpAlgorithm) -> Self {
+        match algorithm {
+            TotpAlgorithm::Sha1 => totp_rs::Algorithm::SHA1,
+            TotpAlgorithm::Sha256 => totp_rs::Algorithm::SHA256,
+            TotpAlgorithm::Sha512 => totp_rs::Algorithm::SHA512,
+        }
+    }
+}
+
+pub fn build(settings: &TotpSettings, secret: Vec<u8>, app_name: &str) -> Result<TOTP, AuthError> {
+    TOTP::new(
+        settings.algorithm.into(),
+        settings.digits,
+        settings.skew,
+        settings.step_secs,
+        secret,
+        Some(app_name.to_string()),
+        app_name.to_string(),
+    )
+    .context("error creating totp")
+    .map_err(AuthError::from)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Time step `code` was generated in, if it's valid now (give or take `skew` steps).
+pub fn matching_step(totp: &TOTP, code: &str) -> Option<u64> {
+    matching_step_at(totp, code, Utc::now().timestamp() as u64)
+}
+
+fn matching_step_at(totp: &TOTP, code: &str, timestamp: u64) -> Option<u64> {
+    let step = timestamp / totp.step;
+    (step.saturating_sub(totp.skew as u64)..=step + totp.skew as u64)
+        .find(|step| constant_time_eq(totp.generate(step * totp.step).as_bytes(), code.as_bytes()))
+}
+
+/// Check `code` against the enrolled secret of `user_id`.
+///
+/// A code is accepted once: its time step has to be after the last accepted one.
+pub async fn verify(state: &AppState, user_id: Uuid, code: &str) -> Result<bool, AuthError> {
+    let totp_secret = sqlx::query_scalar!("SELECT totp_secret FROM users WHERE id=$1", user_id)
+        .fetch_one(&state.db_pool)
+        .await
+        .context("can't get totp")?;
+    let totp = build(&state.totp, totp_secret, &state.app_name)?;
+    let Some(step) = matching_step(&totp, code) else {
+        return Ok(false);
+    };
+    let step = step as i64;
+    let accepted = sqlx::query!(
+        r#"UPDATE users SET totp_last_step=$1
+        WHERE id=$2 AND (totp_last_step IS NULL OR totp_last_step < $1)"#,
+        step,
+        user_id,
+    )
+    .execute(&state.db_pool)
+    .await
+    .context("can't record totp step")?
+    .rows_affected()
+        > 0;
+    if !accepted {
+        tracing::warn!("replayed totp code for user {}", user_id);
+    }
+    Ok(accepted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 21] = [7; 21];
+
+    fn totp(settings: &TotpSettings) -> TOTP {
+        build(settings, SECRET.to_vec(), "wol_server").unwrap()
+    }
+
+    fn settings() -> TotpSettings {
+        TotpSettings {
+            algorithm: TotpAlgorithm::Sha1,
+            digits: 6,
+            skew: 1,
+            step_secs: 30,
+        }
+    }
+
+    #[test]
+    fn codes_within_the_skew_are_accepted() {
+        let totp = totp(&settings());
+        let now: u64 = 1_700_000_000;
+        let step = now / 30;
+        for offset in [-1, 0, 1] {
+            let code_step = step.checked_add_signed(offset).unwrap();
+            let code = totp.generate(code_step * 30);
+            assert_eq!(matching_step_at(&totp, &code, now), Some(code_step));
+        }
+        for offset in [-2, 2] {
+            let code = totp.generate(step.checked_add_signed(offset).unwrap() * 30);
+            assert_eq!(matching_step_at(&totp, &code, now), None);
+        }
+    }
+
+    #[test]
+    fn other_codes_are_refused() {
+        let totp = totp(&settings());
+        let now: u64 = 1_700_000_000;
+        let code = totp.generate(now);
+        assert_eq!(matching_step_at(&totp, "", now), None);
+        assert_eq!(matching_step_at(&totp, &code[..5], now), None);
+        assert_eq!(matching_step_at(&totp, &format!("{code}0"), now), None);
+        let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+        assert_eq!(matching_step_at(&totp, &wrong, now), None);
+    }
+
+    #[tokio::test]
+    async fn a_code_is_only_accepted_once() {
+        let state = crate::app_state::AppState::for_tests().await;
+        let user_id = Uuid::now_v7();
+        let secret = SECRET.to_vec();
+        sqlx::query!(
+            r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret, active)
+            VALUES ($1, 'user', 'alice', '', 'alice@example.com', 'Alice', $2, 1)"#,
+            user_id,
+            secret,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        let totp = build(&state.totp, SECRET.to_vec(), &state.app_name).unwrap();
+        let step = Utc::now().timestamp() as u64 / totp.step;
+        let code = |step: u64| totp.generate(step * totp.step);
+
+        assert!(verify(&state, user_id, &code(step)).await.unwrap());
+        // replayed, or older than the last accepted one
+        assert!(!verify(&state, user_id, &code(step)).await.unwrap());
+        assert!(!verify(&state, user_id, &code(step - 1)).await.unwrap());
+        assert!(verify(&state, user_id, &code(step + 1)).await.unwrap());
+    }
+}
@@ -0,0 +1,37 @@
+use super::ceremonies::Ceremonies;
+use crate::configuration::WebauthnSettings;
+use std::time::Duration;
+use uuid::Uuid;
+use webauthn_rs::{
+    prelude::{PasskeyAuthentication, PasskeyRegistration, Url, WebauthnError},
+    Webauthn, WebauthnBuilder,
+};
+
+/// How long a started ceremony can be finished, browsers time out after 5 minutes.
+const CEREMONY_TTL: Duration = Duration::from_secs(300);
+
+/// Passkeys as a second factor, next to totp.
+pub struct WebauthnState {
+    pub webauthn: Webauthn,
+    /// Credential name and registration state.
+    pub registrations: Ceremonies<Uuid, (String, PasskeyRegistration)>,
+    pub authentications: Ceremonies<Uuid, PasskeyAuthentication>,
+}
+
+impl WebauthnState {
+    pub fn from_settings(
+        settings: &WebauthnSettings,
+        app_name: &str,
+    ) -> Result<Self, WebauthnError> {
+        let rp_origin =
+            Url::parse(&settings.rp_origin).map_err(|_| WebauthnError::Configuration)?;
+        let webauthn = WebauthnBuilder::new(&settings.rp_id, &rp_origin)?
+            .rp_name(app_name)
+            .build()?;
+        Ok(Self {
+            webauthn,
+            registrations: Ceremonies::new(CEREMONY_TTL),
+            authentications: Ceremonies::new(CEREMONY_TTL),
+        })
+    }
+}
@@ -1,8 +1,10 @@
+use crate::{auth::totp::TotpAlgorithm, probe::ProbeMethod};
 use config::{Config, ConfigError, File};
 use serde::Deserialize;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
 use std::convert::TryFrom;
 use std::net::IpAddr;
+use std::num::NonZeroU64;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tracing_log::log::Level;
@@ -12,6 +14,21 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub logging: LoggingSettings,
+    pub wol: WolSettings,
+    pub prober: ProberSettings,
+    pub rate_limit: RateLimitSettings,
+    pub scheduler: SchedulerSettings,
+    pub totp: TotpSettings,
+    pub mail: MailSettings,
+    pub password_reset: PasswordResetSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub webauthn: WebauthnSettings,
+    /// Login with an OpenID Connect provider, disabled without the section.
+    pub oidc: Option<OidcSettings>,
+    /// Check passwords against a directory instead of the local store, disabled without the section.
+    pub ldap: Option<LdapSettings>,
+    /// Sign jwts with asymmetric keys, `auth_secret` signs them without the section.
+    pub jwt: Option<JwtSettings>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -23,6 +40,205 @@ pub struct ApplicationSettings {
     pub app_name: String,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct WolSettings {
+    pub broadcast_address: IpAddr,
+    pub port: u16,
+    /// Secret the SecureOn passwords encryption key is derived from.
+    pub secure_on_secret: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProberSettings {
+    pub enabled: bool,
+    /// Seconds between two probes of the same device.
+    pub interval_secs: u64,
+    pub timeout_ms: u64,
+    pub method: ProbeMethod,
+    pub tcp_port: u16,
+    /// Upper bound for waiting on a device to boot after a magic packet.
+    pub wake_timeout_secs: u64,
+    /// Milliseconds between probes while waiting on a device to boot.
+    pub wake_poll_ms: NonZeroU64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MailSettings {
+    /// Sender mailbox, e.g. `wol_server <noreply@example.com>`.
+    pub from: String,
+    pub backend: MailBackend,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum MailBackend {
+    /// Mails are only logged, for local development: refused in prod.
+    Log,
+    /// Mails are written as `.eml` files in `directory`.
+    File {
+        directory: PathBuf,
+    },
+    Smtp(SmtpSettings),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text, only for a relay on localhost.
+    None,
+    Starttls,
+    Tls,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PasswordResetSettings {
+    /// Minutes an emailed reset token stays valid.
+    pub token_ttl_mins: u64,
+}
+
+/// Changing any of these invalidates every enrolled authenticator but `skew`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TotpSettings {
+    pub algorithm: TotpAlgorithm,
+    pub digits: usize,
+    /// Steps before and after the current one still accepted, for clock drift.
+    pub skew: u8,
+    pub step_secs: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SchedulerSettings {
+    /// Run the background task firing the device wake-up schedules.
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RateLimitSettings {
+    pub login: BucketSettings,
+    pub power_on: BucketSettings,
+    pub device_refresh: BucketSettings,
+    pub password_reset: BucketSettings,
+}
+
+/// Relying party of the passkeys, changing `rp_id` invalidates every registered passkey.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebauthnSettings {
+    /// Domain of the frontend, e.g. `wol.example.com`.
+    pub rp_id: String,
+    /// Origin the frontend is served from, e.g. `https://wol.example.com`.
+    pub rp_origin: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct OidcSettings {
+    /// Shown on the login button.
+    pub provider_name: String,
+    /// The provider metadata is at `{issuer_url}/.well-known/openid-configuration`.
+    pub issuer_url: String,
+    pub client_id: String,
+    /// Not needed by public clients, PKCE protects the code exchange.
+    pub client_secret: Option<String>,
+    /// `/api/auth/oidc/callback` of this server, as registered at the provider.
+    pub redirect_url: String,
+    /// Frontend page the browser lands on, with `?status=ok` or `?status=pending`.
+    pub post_login_url: String,
+    /// Link a provider account to the user with the same verified email.
+    pub link_by_email: bool,
+}
+
+/// Keys of the jwts, published at `/.well-known/jwks.json`.
+///
+/// To rotate, add the new key, sign with it and drop the private key of the old one: its tokens
+/// stay valid until they expire. Remove the old key after the refresh token lifetime (30 days).
+#[derive(Deserialize, Clone, Debug)]
+pub struct JwtSettings {
+    /// `kid` of the key new tokens are signed with, it needs a private key.
+    pub signing_kid: String,
+    pub keys: Vec<JwtKeySettings>,
+    /// Keep accepting tokens signed with `auth_secret`, while sessions from before the switch expire.
+    pub accept_auth_secret: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct JwtKeySettings {
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    /// PEM file of the public key.
+    pub public_key_path: PathBuf,
+    /// PEM file of the private key, only needed by the signing key.
+    pub private_key_path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    EdDSA,
+    RS256,
+}
+
+/// Search-then-bind against a directory: the user entry is searched by email with the service
+/// account, then the password is checked by binding as that entry.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LdapSettings {
+    /// `ldap://` or `ldaps://` url of the directory.
+    pub url: String,
+    /// Upgrade an `ldap://` connection with StartTLS.
+    pub starttls: bool,
+    pub timeout_secs: u64,
+    /// Service account for the search, an anonymous search without it.
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    pub user_base_dn: String,
+    /// `{email}` is replaced with the escaped login email, e.g. `(&(objectClass=person)(mail={email}))`.
+    pub user_filter: String,
+    /// Attribute of the user entry listing its groups, e.g. `memberOf`.
+    pub group_attribute: String,
+    /// Roles given to members of a group, a user gets the roles of all its groups.
+    pub group_roles: Vec<LdapGroupRoles>,
+    /// Roles of users in none of `group_roles`, an empty list refuses them.
+    pub default_roles: Vec<String>,
+    /// Let users unknown to the directory log in with a local password, e.g. a break-glass admin.
+    pub local_fallback: bool,
+    /// Link a directory entry to the local account with the same email, its roles then come
+    /// from the directory. Without it, such a login is refused.
+    pub link_by_email: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LdapGroupRoles {
+    /// Group dn, compared case-insensitively.
+    pub group: String,
+    pub roles: Vec<String>,
+}
+
+/// Failed logins allowed per account and per client ip before a lockout.
+///
+/// The lockout starts at `base_lockout_secs` and doubles with each further failure.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LoginThrottleSettings {
+    pub account_threshold: u32,
+    pub ip_threshold: u32,
+    pub base_lockout_secs: u64,
+    pub max_lockout_secs: u64,
+    /// Failures are forgotten after that long without a new one.
+    pub forget_after_mins: u64,
+}
+
+/// Token bucket: up to `capacity` requests in a burst, refilled by `per_minute` tokens a minute.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BucketSettings {
+    pub capacity: u32,
+    pub per_minute: u32,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     location: PathBuf,
@@ -124,5 +340,13 @@ pub fn load_settings(config_path: &Path) -> Result<Settings, ConfigError> {
         )
         .build()?;
 
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    // it writes the password reset links to the logs
+    if matches!(environment, Environment::Prod) && matches!(settings.mail.backend, MailBackend::Log)
+    {
+        return Err(ConfigError::Message(
+            "the log mail backend is only for dev, configure [mail.backend]".to_string(),
+        ));
+    }
+    Ok(settings)
 }
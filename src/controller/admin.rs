@@ -1 +1,2 @@
+pub mod sessions;
 pub mod user_requests;
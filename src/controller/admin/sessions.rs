@@ -0,0 +1,50 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::ctx::Ctx,
+    controller::{app::session, error::SessionError},
+    model::{api_token::ApiToken, session::Session},
+};
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+
+#[tracing::instrument(skip_all, fields(admin_id = %ctx.user_id, user_id = %user_id))]
+pub async fn get(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<Session>>, SessionError> {
+    let sessions = Session::list_active(&state.db_pool, user_id, ctx.sid)
+        .await
+        .context("can't list sessions")?;
+    Ok(Json(sessions))
+}
+
+/// Sign the user out everywhere, api tokens included.
+#[tracing::instrument(skip_all, fields(admin_id = %ctx.user_id, user_id = %user_id))]
+pub async fn delete(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, SessionError> {
+    Session::revoke_all(&state.db_pool, user_id, None)
+        .await
+        .context("can't revoke sessions")?;
+    ApiToken::delete_all(&state.db_pool, user_id)
+        .await
+        .context("can't delete api tokens")?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(skip_all, fields(admin_id = %ctx.user_id, user_id = %user_id, session_id = %session_id))]
+pub async fn delete_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path((user_id, session_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, SessionError> {
+    session::revoke(&state, user_id, session_id).await
+}
@@ -1,51 +1,103 @@
 use crate::{
-    app_state::SharedAppState, auth::ctx::Ctx, controller::error::UnknownError,
-    model::user_request::UserSignupRequest,
+    app_state::SharedAppState,
+    auth::ctx::Ctx,
+    controller::error::UserRequestError,
+    model::user_request::{UserRequest, UserRequestFilter},
 };
 use anyhow::Context;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
+    Json,
 };
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use uuid::Uuid;
 
-#[tracing::instrument(name = "users_signup_requests", skip_all)]
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UserRequestsQuery {
+    /// `next_cursor` of the previous page.
+    cursor: Option<Uuid>,
+    limit: Option<u32>,
+    search: Option<String>,
+    requested_from: Option<NaiveDate>,
+    requested_to: Option<NaiveDate>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UserRequestsPage {
+    items: Vec<UserRequest>,
+    /// Missing on the last page.
+    next_cursor: Option<Uuid>,
+}
+
+#[tracing::instrument(name = "users_signup_requests", skip_all, fields(admin_id = %ctx.user_id))]
 pub async fn get(
     State(state): State<SharedAppState>,
     ctx: Ctx,
-) -> Result<Vec<UserSignupRequest>, UnknownError> {
-    tracing::info!("{}", ctx.user_id);
-    let signups = sqlx::query_as!(
-        UserSignupRequest,
-        r#"SELECT user_id as "user_id: Uuid",request_text from users_signup_requests"#
-    )
-    .fetch_all(&state.db_pool)
-    .await
-    .context("can't query user signup requests")?;
-    Ok(signups)
+    Query(query): Query<UserRequestsQuery>,
+) -> Result<Json<UserRequestsPage>, UserRequestError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let filter = UserRequestFilter {
+        search: query.search.filter(|search| !search.trim().is_empty()),
+        requested_from: query.requested_from,
+        requested_to: query.requested_to,
+    };
+    // one more row tells if there's a next page
+    let mut items = UserRequest::list_page(&state.db_pool, &filter, query.cursor, limit + 1)
+        .await
+        .context("can't query user signup requests")?;
+    let next_cursor = match items.len() > limit as usize {
+        true => {
+            items.truncate(limit as usize);
+            items.last().map(|request| request.user_id)
+        }
+        false => None,
+    };
+    Ok(Json(UserRequestsPage { items, next_cursor }))
 }
 
-#[tracing::instrument(name = "request_accept", skip_all)]
-pub async fn get_accept(
+#[tracing::instrument(name = "users_signup_request", skip_all, fields(admin_id = %ctx.user_id, user_id = %user_id))]
+pub async fn get_by_id(
     State(state): State<SharedAppState>,
     ctx: Ctx,
     Path(user_id): Path<Uuid>,
-) -> Result<StatusCode, UnknownError> {
-    tracing::info!("{}", ctx.user_id);
+) -> Result<Json<UserRequest>, UserRequestError> {
+    let request = UserRequest::fetch(&state.db_pool, user_id)
+        .await
+        .context("can't query user signup request")?
+        .ok_or(UserRequestError::NotFound)?;
+    Ok(Json(request))
+}
+
+#[tracing::instrument(name = "request_accept", skip_all, fields(admin_id = %ctx.user_id, user_id = %user_id))]
+pub async fn post_accept(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, UserRequestError> {
     let mut transaction = state
         .db_pool
         .begin()
         .await
         .context("can't start transaction")?;
 
-    sqlx::query!(
+    let deleted = sqlx::query!(
         r#"DELETE FROM users_signup_requests WHERE user_id=$1"#,
         user_id
     )
     .execute(&mut *transaction)
     .await
-    .context("can't accept uuid request")?;
+    .context("can't accept uuid request")?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(UserRequestError::NotFound);
+    }
 
     sqlx::query!(
         r#"UPDATE users SET active=1, join_date=datetime('now','localtime') WHERE id=$1"#,
@@ -69,26 +121,29 @@ struct UserInfos {
     request_date: NaiveDateTime,
 }
 
-#[tracing::instrument(name = "request_reject", skip_all)]
-pub async fn get_reject(
+#[tracing::instrument(name = "request_reject", skip_all, fields(admin_id = %ctx.user_id, user_id = %user_id))]
+pub async fn post_reject(
     State(state): State<SharedAppState>,
     ctx: Ctx,
     Path(user_id): Path<Uuid>,
-) -> Result<StatusCode, UnknownError> {
-    tracing::info!("{}", ctx.user_id);
+) -> Result<StatusCode, UserRequestError> {
     let mut transaction = state
         .db_pool
         .begin()
         .await
         .context("can't start transaction")?;
 
-    sqlx::query!(
+    let deleted = sqlx::query!(
         r#"DELETE FROM users_signup_requests WHERE user_id=$1"#,
         user_id
     )
     .execute(&mut *transaction)
     .await
-    .context("can't accept uuid request")?;
+    .context("can't accept uuid request")?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(UserRequestError::NotFound);
+    }
 
     let user_infos = sqlx::query_as!(
         UserInfos,
@@ -115,3 +170,99 @@ pub async fn get_reject(
 
     Ok(StatusCode::OK)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::AppState, model::role::Role};
+
+    async fn request_signup(state: &AppState, username: &str) -> Uuid {
+        let user_id = Uuid::now_v7();
+        let email = format!("{username}@example.com");
+        sqlx::query!(
+            r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret)
+            VALUES ($1, 'user', $2, '', $3, $2, x'00')"#,
+            user_id,
+            username,
+            email,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO users_signup_requests(user_id, request_text) VALUES ($1, 'hi')",
+            user_id,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        user_id
+    }
+
+    fn query(cursor: Option<Uuid>, limit: u32, search: Option<&str>) -> UserRequestsQuery {
+        UserRequestsQuery {
+            cursor,
+            limit: Some(limit),
+            search: search.map(str::to_string),
+            requested_from: None,
+            requested_to: None,
+        }
+    }
+
+    async fn page(state: &SharedAppState, query: UserRequestsQuery) -> UserRequestsPage {
+        let admin = Ctx::new(Uuid::now_v7(), vec![Role::Admin]);
+        get(State(state.clone()), admin, Query(query))
+            .await
+            .unwrap()
+            .0
+    }
+
+    fn ids(page: &UserRequestsPage) -> Vec<Uuid> {
+        page.items.iter().map(|request| request.user_id).collect()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_the_request_order() {
+        let state = AppState::for_tests().await;
+        let mut requests = Vec::new();
+        for username in ["carol", "alice", "dave", "bob", "erin"] {
+            requests.push(request_signup(&state, username).await);
+        }
+
+        let first = page(&state, query(None, 2, None)).await;
+        assert_eq!(ids(&first), requests[..2]);
+        assert_eq!(first.next_cursor, Some(requests[1]));
+        let second = page(&state, query(first.next_cursor, 2, None)).await;
+        assert_eq!(ids(&second), requests[2..4]);
+        let last = page(&state, query(second.next_cursor, 2, None)).await;
+        assert_eq!(ids(&last), requests[4..]);
+        assert_eq!(last.next_cursor, None);
+
+        // a full last page has no next page either
+        let all = page(&state, query(None, 5, None)).await;
+        assert_eq!(ids(&all), requests);
+        assert_eq!(all.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn wildcards_in_the_search_are_literal() {
+        let state = AppState::for_tests().await;
+        let underscore = request_signup(&state, "a_b").await;
+        let percent = request_signup(&state, "a%b").await;
+        request_signup(&state, "axb").await;
+        let backslash = request_signup(&state, "a\\b").await;
+
+        assert_eq!(
+            ids(&page(&state, query(None, 10, Some("a_b"))).await),
+            [underscore]
+        );
+        assert_eq!(
+            ids(&page(&state, query(None, 10, Some("A%"))).await),
+            [percent]
+        );
+        assert_eq!(
+            ids(&page(&state, query(None, 10, Some("a\\b"))).await),
+            [backslash]
+        );
+    }
+}
@@ -1,2 +1,6 @@
+pub mod api_token;
 pub mod auth;
+pub mod device;
 pub mod profile;
+pub mod schedule;
+pub mod session;
@@ -0,0 +1,95 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{api_token, ctx::Ctx, opaque_token},
+    controller::error::ApiTokenError,
+    model::api_token::{ApiScope, ApiToken, NewApiToken},
+};
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+const NAME_MAX_LEN: usize = 64;
+const DEFAULT_EXPIRATION_DAYS: u32 = 90;
+const MAX_EXPIRATION_DAYS: u32 = 365;
+
+#[derive(serde::Serialize)]
+pub struct CreatedApiToken {
+    /// Shown once, only its hash is stored.
+    token: String,
+    #[serde(flatten)]
+    api_token: ApiToken,
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn get(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+) -> Result<Json<Vec<ApiToken>>, ApiTokenError> {
+    let api_tokens = ApiToken::list_for(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't list api tokens")?;
+    Ok(Json(api_tokens))
+}
+
+/// Create a token, it grants the scopes without totp: like the other routes, a user with a
+/// second factor needs a validated session, see [`crate::middleware::mw_auth::totp_must_be_valid`].
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn post(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(new_token): Json<NewApiToken>,
+) -> Result<(StatusCode, Json<CreatedApiToken>), ApiTokenError> {
+    let name = new_token.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > NAME_MAX_LEN {
+        return Err(ApiTokenError::InvalidName(NAME_MAX_LEN));
+    }
+    if new_token.scopes.is_empty() {
+        return Err(ApiTokenError::MissingScopes);
+    }
+    let expiration_days = new_token.expires_in_days.unwrap_or(DEFAULT_EXPIRATION_DAYS);
+    if !(1..=MAX_EXPIRATION_DAYS).contains(&expiration_days) {
+        return Err(ApiTokenError::InvalidExpiration(MAX_EXPIRATION_DAYS));
+    }
+
+    let token = api_token::generate();
+    let created_at = Utc::now();
+    // sorted and deduplicated like the stored ones
+    let scopes = ApiScope::parse_scopes(&ApiScope::join(&new_token.scopes));
+    let api_token = ApiToken {
+        id: Uuid::now_v7(),
+        user_id: ctx.user_id,
+        name,
+        scopes,
+        created_at,
+        expires_at: created_at + Duration::days(expiration_days as i64),
+        last_used_at: None,
+    };
+    api_token
+        .insert(&state.db_pool, opaque_token::hash(&token))
+        .await
+        .context("can't create api token")?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiToken { token, api_token }),
+    ))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, api_token_id = %api_token_id))]
+pub async fn delete_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(api_token_id): Path<Uuid>,
+) -> Result<StatusCode, ApiTokenError> {
+    let deleted = ApiToken::delete(&state.db_pool, ctx.user_id, api_token_id)
+        .await
+        .context("can't delete api token")?;
+    match deleted {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiTokenError::NotFound),
+    }
+}
@@ -1,5 +1,9 @@
 pub mod login;
 pub mod logout;
+pub mod oidc;
+pub mod password;
+pub mod password_reset;
 pub mod refresh;
 pub mod signup;
 pub mod totp;
+pub mod webauthn;
@@ -2,46 +2,75 @@ use crate::{
     app_state::SharedAppState,
     auth::{
         ctx::Ctx,
+        error::AuthError,
         password::{validate_credentials, Credentials},
-        REFRESH_COOKIE,
+        refresh_token::{self, ClientInfo},
     },
     controller::error::GenericAuthError,
 };
+use anyhow::Context;
 use axum::{
     extract::State,
     http::StatusCode,
     response::{IntoResponse, Response},
     Form,
 };
-use jsonwebtoken::EncodingKey;
 use serde_json::json;
-use tower_cookies::cookie::{time::Duration, SameSite};
-use tower_cookies::{Cookie, Cookies};
+use tower_cookies::Cookies;
 
 #[tracing::instrument(skip_all)]
 pub async fn post(
     State(state): State<SharedAppState>,
     ctx: Option<Ctx>,
     cookies: Cookies,
+    client: ClientInfo,
     Form(credentials): Form<Credentials>,
 ) -> Result<Response, GenericAuthError> {
     if ctx.is_some() {
         return Ok(StatusCode::OK.into_response());
     }
-    let mut user_ctx = validate_credentials(credentials, &state.db_pool).await?;
+    let ip = client.ip_address.as_deref().and_then(|ip| ip.parse().ok());
+    let email = credentials.email.clone();
+    state.login_throttle.check(&email, ip)?;
+    let user_ctx = match validate_credentials(
+        state.credentials_backend.as_ref(),
+        credentials,
+        &state.db_pool,
+    )
+    .await
+    {
+        Ok(user_ctx) => {
+            state.login_throttle.record_success(&email);
+            user_ctx
+        }
+        Err(AuthError::InvalidCredentials(error)) => {
+            state.login_throttle.record_failure(&email, ip);
+            return Err(AuthError::InvalidCredentials(error).into());
+        }
+        Err(error) => return Err(error.into()),
+    };
     tracing::Span::current().record("user_id", tracing::field::display(&user_ctx.user_id));
-    let auth_jwt = user_ctx
-        .as_auth()
-        .to_jwt(EncodingKey::from_secret(state.auth_secret.as_bytes()))?;
-    let refresh_jwt = user_ctx
-        .as_refresh()
-        .to_jwt(EncodingKey::from_secret(state.auth_secret.as_bytes()))?;
-    let refresh_cookie = Cookie::build((REFRESH_COOKIE, refresh_jwt))
-        .max_age(Duration::days(30))
-        .same_site(SameSite::Lax)
-        .path("/")
-        .http_only(true)
-        .build();
-    cookies.add(refresh_cookie);
-    Ok((json!({"jwt":auth_jwt,"ctx":ctx}).to_string()).into_response())
+    let (mut user_ctx, refresh_jwt) = refresh_token::issue(&state, &user_ctx, &client).await?;
+    let auth_jwt = user_ctx.as_auth().to_jwt(&state.jwt_keys)?;
+    cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+    // the frontend asks for the second factor or a new password next
+    let status = sqlx::query!(
+        r#"SELECT force_password_reset, totp_enrolled as "totp_enrolled: bool",
+            EXISTS (SELECT 1 FROM webauthn_credentials WHERE user_id = users.id)
+                as "webauthn_enrolled!: bool"
+        FROM users WHERE id=$1"#,
+        user_ctx.user_id
+    )
+    .fetch_one(&state.db_pool)
+    .await
+    .context("can't fetch user status")?;
+    Ok((json!({
+        "jwt": auth_jwt,
+        "ctx": ctx,
+        "force_password_reset": status.force_password_reset,
+        "totp_enrolled": status.totp_enrolled,
+        "webauthn_enrolled": status.webauthn_enrolled,
+    })
+    .to_string())
+    .into_response())
 }
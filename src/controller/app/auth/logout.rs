@@ -1,11 +1,17 @@
-use crate::auth::logout;
-use axum::http::StatusCode;
+use crate::{app_state::SharedAppState, auth::logout};
+use axum::{extract::State, http::StatusCode};
 use tower_cookies::Cookies;
 
 #[tracing::instrument(skip_all)]
-pub async fn post(cookies: Cookies) -> Result<StatusCode, StatusCode> {
-    match logout::logout(cookies).await {
+pub async fn post(
+    State(state): State<SharedAppState>,
+    cookies: Cookies,
+) -> Result<StatusCode, StatusCode> {
+    match logout::logout(&state, cookies).await {
         Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
     }
 }
@@ -0,0 +1,110 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{
+        error::OidcError,
+        oidc::{OidcClient, OidcLogin},
+        refresh_token::{self, ClientInfo},
+    },
+};
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use reqwest::Url;
+use serde_json::{json, Value};
+use tower_cookies::{
+    cookie::{time::Duration, SameSite},
+    Cookie, Cookies,
+};
+
+/// Binds the login to the browser that started it.
+const OIDC_STATE_COOKIE: &str = "WOL_OIDC_STATE";
+const OIDC_COOKIE_PATH: &str = "/api/auth/oidc";
+
+#[derive(serde::Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+fn oidc_client(state: &SharedAppState) -> Result<&OidcClient, OidcError> {
+    state.oidc.as_ref().ok_or(OidcError::Disabled)
+}
+
+/// The provider the frontend offers to log in with.
+#[tracing::instrument(skip_all)]
+pub async fn get(State(state): State<SharedAppState>) -> Result<Json<Value>, OidcError> {
+    let oidc = oidc_client(&state)?;
+    Ok(Json(json!({"provider_name": oidc.settings.provider_name})))
+}
+
+/// Redirect the browser to the provider.
+#[tracing::instrument(skip_all)]
+pub async fn get_login(
+    State(state): State<SharedAppState>,
+    cookies: Cookies,
+) -> Result<Response, OidcError> {
+    let (url, login_state) = oidc_client(&state)?.authorization_url().await?;
+    cookies.add(
+        Cookie::build((OIDC_STATE_COOKIE, login_state))
+            .max_age(Duration::minutes(10))
+            // the provider redirects back with a top-level navigation
+            .same_site(SameSite::Lax)
+            .path(OIDC_COOKIE_PATH)
+            .http_only(true)
+            .build(),
+    );
+    Ok(Redirect::to(url.as_str()).into_response())
+}
+
+/// The provider redirects here, the user gets a refresh cookie unless the signup is pending.
+#[tracing::instrument(skip_all)]
+pub async fn get_callback(
+    State(state): State<SharedAppState>,
+    cookies: Cookies,
+    client: ClientInfo,
+    Query(callback): Query<CallbackQuery>,
+) -> Result<Response, OidcError> {
+    let oidc = oidc_client(&state)?;
+    let expected_state = cookies
+        .get(OIDC_STATE_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    cookies.remove(
+        Cookie::build(OIDC_STATE_COOKIE)
+            .path(OIDC_COOKIE_PATH)
+            .build(),
+    );
+    if let Some(error) = callback.error {
+        return Err(OidcError::Denied(
+            callback
+                .error_description
+                .map(|description| format!("{}: {}", error, description))
+                .unwrap_or(error),
+        ));
+    }
+    let (Some(code), Some(login_state)) = (callback.code, callback.state) else {
+        return Err(OidcError::InvalidState);
+    };
+    if expected_state.as_deref() != Some(login_state.as_str()) {
+        return Err(OidcError::InvalidState);
+    }
+
+    let claims = oidc.exchange(&login_state, &code).await?;
+    let status = match oidc.resolve_user(&state.db_pool, &claims).await? {
+        OidcLogin::Active(ctx) => {
+            tracing::Span::current().record("user_id", tracing::field::display(&ctx.user_id));
+            // the second factor is still asked, like after a password login
+            let (_, refresh_jwt) = refresh_token::issue(&state, &ctx, &client).await?;
+            cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+            "ok"
+        }
+        OidcLogin::Pending => "pending",
+    };
+    let mut url = Url::parse(&oidc.settings.post_login_url).context("invalid post login url")?;
+    url.query_pairs_mut().append_pair("status", status);
+    Ok(Redirect::to(url.as_str()).into_response())
+}
@@ -0,0 +1,49 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{
+        ctx::Ctx,
+        password::{is_password_strong, update_password, verify_password},
+    },
+    controller::error::PasswordChangeError,
+    model::{api_token::ApiToken, session::Session},
+};
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode, Json};
+
+#[derive(serde::Deserialize)]
+pub struct PasswordChange {
+    old_password: String,
+    new_password: String,
+}
+
+/// Change the password, the other sessions of the user are signed out
+/// and the api tokens deleted.
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn post(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(change): Json<PasswordChange>,
+) -> Result<StatusCode, PasswordChangeError> {
+    let stored_password =
+        sqlx::query_scalar!("SELECT password FROM users WHERE id=$1", ctx.user_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .context("can't fetch password")?;
+    verify_password(stored_password, change.old_password.clone())
+        .await
+        .map_err(|_| PasswordChangeError::WrongPassword)?;
+    if change.new_password == change.old_password {
+        return Err(PasswordChangeError::SamePassword);
+    }
+    if !is_password_strong(&change.new_password) {
+        return Err(PasswordChangeError::WeakPassword);
+    }
+    update_password(&state.db_pool, ctx.user_id, &change.new_password).await?;
+    Session::revoke_all(&state.db_pool, ctx.user_id, ctx.sid)
+        .await
+        .context("can't revoke sessions")?;
+    ApiToken::delete_all(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't delete api tokens")?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,63 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{
+        password::{is_password_strong, update_password},
+        password_reset,
+    },
+    controller::error::PasswordResetError,
+    model::{api_token::ApiToken, session::Session},
+};
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode, Form};
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPassword {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordReset {
+    token: String,
+    new_password: String,
+}
+
+/// Mail a reset link, the answer is the same whether the address is known or not.
+#[tracing::instrument(skip_all)]
+pub async fn post_forgot(
+    State(state): State<SharedAppState>,
+    Form(forgot): Form<ForgotPassword>,
+) -> StatusCode {
+    // sent in the background, the response time doesn't tell if the address exists
+    tokio::spawn(async move {
+        if let Err(error) = password_reset::send_reset_mail(&state, &forgot.email).await {
+            tracing::error!("can't send password reset: {:?}", error);
+        }
+    });
+    StatusCode::ACCEPTED
+}
+
+/// Set a new password with an emailed token, every session of the user is signed out
+/// and the api tokens deleted.
+#[tracing::instrument(skip_all)]
+pub async fn post_reset(
+    State(state): State<SharedAppState>,
+    Form(reset): Form<PasswordReset>,
+) -> Result<StatusCode, PasswordResetError> {
+    // checked first, a weak password doesn't burn the token
+    if !is_password_strong(&reset.new_password) {
+        return Err(PasswordResetError::WeakPassword);
+    }
+    let user_id = password_reset::consume_token(&state.db_pool, &reset.token)
+        .await
+        .context("can't consume reset token")?
+        .ok_or(PasswordResetError::InvalidToken)?;
+    update_password(&state.db_pool, user_id, &reset.new_password).await?;
+    Session::revoke_all(&state.db_pool, user_id, None)
+        .await
+        .context("can't revoke sessions")?;
+    ApiToken::delete_all(&state.db_pool, user_id)
+        .await
+        .context("can't delete api tokens")?;
+    tracing::info!("password reset for user {}", user_id);
+    Ok(StatusCode::NO_CONTENT)
+}
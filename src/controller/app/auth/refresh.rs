@@ -1,6 +1,11 @@
 use crate::{
     app_state::SharedAppState,
-    auth::{self, ctx::Ctx, error::AuthError, AUTH_HEADER},
+    auth::{
+        self,
+        error::AuthError,
+        refresh_token::{self, ClientInfo},
+        AUTH_HEADER,
+    },
     controller::error::GenericAuthError,
 };
 use axum::{
@@ -8,7 +13,6 @@ use axum::{
     http::HeaderMap,
     response::{IntoResponse, Response},
 };
-use jsonwebtoken::{DecodingKey, EncodingKey};
 use serde_json::json;
 use tower_cookies::Cookies;
 
@@ -16,6 +20,7 @@ use tower_cookies::Cookies;
 pub async fn get(
     State(state): State<SharedAppState>,
     cookies: Cookies,
+    client: ClientInfo,
 ) -> Result<Response, GenericAuthError> {
     let refresh_cookie =
         cookies
@@ -23,14 +28,11 @@ pub async fn get(
             .ok_or(GenericAuthError::GenericAuthError(
                 AuthError::MissingCredentials,
             ))?;
-    let mut ctx = Ctx::from_jwt(
-        refresh_cookie.value(),
-        &DecodingKey::from_secret(state.auth_secret.as_bytes()),
-    )?;
-    let auth_jwt = ctx
-        .as_auth()
-        .to_jwt(EncodingKey::from_secret(state.auth_secret.as_bytes()))?;
-    dbg!(&auth_jwt);
+    // rotate: the presented token can't be used again
+    let ctx = refresh_token::consume(&state, refresh_cookie.value()).await?;
+    let (mut ctx, refresh_jwt) = refresh_token::issue(&state, &ctx, &client).await?;
+    cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+    let auth_jwt = ctx.as_auth().to_jwt(&state.jwt_keys)?;
     let mut headers = HeaderMap::new();
     headers.append(AUTH_HEADER, auth_jwt.parse().expect("can't parse auth"));
     Ok((headers, json!({"jwt":auth_jwt,"ctx":ctx}).to_string()).into_response())
@@ -1,6 +1,12 @@
 use crate::{
     app_state::SharedAppState,
-    auth::{ctx::Ctx, error::AuthError, REFRESH_COOKIE},
+    auth::{
+        ctx::{Ctx, TokenType},
+        error::AuthError,
+        recovery_codes,
+        refresh_token::{self, ClientInfo},
+        totp, REFRESH_COOKIE,
+    },
     controller::error::GenericAuthError,
 };
 use anyhow::Context;
@@ -10,12 +16,9 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::Utc;
-use jsonwebtoken::{DecodingKey, EncodingKey};
 use rand::Rng as _;
 use serde_json::json;
-use tower_cookies::cookie::time::Duration;
-use tower_cookies::{Cookie, Cookies};
+use tower_cookies::Cookies;
 
 #[derive(serde::Deserialize)]
 struct TotpSecret {
@@ -31,6 +34,7 @@ pub struct TotpRequest {
 pub async fn get_regenerate(
     State(state): State<SharedAppState>,
     cookies: Cookies,
+    client: ClientInfo,
     mut ctx: Ctx,
 ) -> Result<Response, GenericAuthError> {
     let mut transaction = state
@@ -49,34 +53,16 @@ pub async fn get_regenerate(
     .execute(&mut *transaction)
     .await
     .context("can't update totp")?;
-    let refresh_jwt =
-        ctx.as_refresh()
-            .with_valid_totp(false)
-            .to_jwt(jsonwebtoken::EncodingKey::from_secret(
-                state.auth_secret.as_bytes(),
-            ))?;
-    let refresh_cookie = Cookie::build((REFRESH_COOKIE, refresh_jwt))
-        .max_age(Duration::days(30))
-        .http_only(true)
-        .path("/")
-        .build();
-    cookies.add(refresh_cookie);
-    let totp = totp_rs::TOTP::new(
-        totp_rs::Algorithm::SHA1,
-        6,
-        1,
-        30,
-        totp_secret,
-        Some(state.app_name.clone()),
-        state.app_name.clone(),
-    )
-    .context("error creating totp url")?;
+    let totp = totp::build(&state.totp, totp_secret, &state.app_name)?;
     let totp_url = totp.get_url();
     let totp_secret = totp.get_secret_base32();
     transaction
         .commit()
         .await
         .context("can't commit transaction")?;
+    let (_, refresh_jwt) =
+        refresh_token::issue(&state, ctx.with_valid_totp(false), &client).await?;
+    cookies.add(refresh_token::refresh_cookie(refresh_jwt));
     Ok(json!({"url":totp_url,"secret":totp_secret})
         .to_string()
         .into_response())
@@ -87,7 +73,8 @@ pub async fn post_validate(
     State(state): State<SharedAppState>,
     mut ctx: Ctx,
     cookies: Cookies,
-    Json(totp): Json<TotpRequest>,
+    client: ClientInfo,
+    Json(totp_request): Json<TotpRequest>,
 ) -> Result<Response, GenericAuthError> {
     let mut transaction = state
         .db_pool
@@ -106,50 +93,46 @@ pub async fn post_validate(
     .context("can't fetch totp")?;
     match totp_secret {
         Some(totp_secret) => {
-            let is_valid = totp_rs::TOTP::new(
-                totp_rs::Algorithm::SHA1,
-                6,
-                1,
-                30,
-                totp_secret.totp_secret.clone(),
-                Some(state.app_name.clone()),
-                state.app_name.clone(),
-            )
-            .context("error creating totp url")?
-            .check(&totp.totp, Utc::now().timestamp() as u64);
-            match is_valid {
-                true => {
-                    let refresh_jwt = ctx.as_refresh().with_valid_totp(true).to_jwt(
-                        jsonwebtoken::EncodingKey::from_secret(state.auth_secret.as_bytes()),
-                    )?;
-                    let auth_token =
-                        ctx.as_auth()
-                            .to_jwt(jsonwebtoken::EncodingKey::from_secret(
-                                state.auth_secret.as_bytes(),
-                            ))?;
+            let step = totp::matching_step(
+                &totp::build(
+                    &state.totp,
+                    totp_secret.totp_secret.clone(),
+                    &state.app_name,
+                )?,
+                &totp_request.totp,
+            );
+            match step {
+                Some(step) => {
+                    let step = step as i64;
                     sqlx::query!(
                         r#"UPDATE users
-                        SET totp_secret=$1
-                        WHERE id=$2"#,
+                        SET totp_secret=$1, totp_last_step=$2, totp_enrolled=1
+                        WHERE id=$3"#,
                         totp_secret.totp_secret,
+                        step,
                         ctx.user_id,
                     )
                     .execute(&mut *transaction)
                     .await
                     .context("can't update totp")?;
+                    // codes of a previous enrolment don't outlive its secret
+                    recovery_codes::delete_all(&mut transaction, ctx.user_id).await?;
                     transaction
                         .commit()
                         .await
                         .context("can't commit transaction")?;
-                    let refresh_cookie = Cookie::build((REFRESH_COOKIE, refresh_jwt))
-                        .max_age(Duration::days(30))
-                        .http_only(true)
-                        .path("/")
-                        .build();
-                    cookies.add(refresh_cookie);
-                    Ok(auth_token.into_response())
+                    let (mut ctx, refresh_jwt) =
+                        refresh_token::issue(&state, ctx.with_valid_totp(true), &client).await?;
+                    cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+                    let auth_token = ctx.as_auth().to_jwt(&state.jwt_keys)?;
+                    // shown once, the user has to store them
+                    let recovery_codes =
+                        recovery_codes::regenerate(&state.db_pool, ctx.user_id).await?;
+                    Ok(json!({"jwt":auth_token,"recovery_codes":recovery_codes})
+                        .to_string()
+                        .into_response())
                 }
-                false => return Ok(StatusCode::BAD_REQUEST.into_response()),
+                None => return Ok(StatusCode::BAD_REQUEST.into_response()),
             }
         }
         None => Ok(StatusCode::BAD_REQUEST.into_response()),
@@ -160,48 +143,56 @@ pub async fn post_validate(
 pub async fn post(
     State(state): State<SharedAppState>,
     cookies: Cookies,
+    client: ClientInfo,
     Json(secret): Json<TotpRequest>,
 ) -> Result<Response, GenericAuthError> {
-    let mut refresh_cookie =
-        cookies
-            .get(REFRESH_COOKIE)
-            .ok_or(GenericAuthError::GenericAuthError(
-                AuthError::MissingCredentials,
-            ))?;
-    let mut ctx = Ctx::from_jwt(
-        refresh_cookie.value(),
-        &DecodingKey::from_secret(state.auth_secret.as_bytes()),
-    )?;
+    let refresh_cookie = cookies
+        .get(REFRESH_COOKIE)
+        .ok_or(GenericAuthError::GenericAuthError(
+            AuthError::MissingCredentials,
+        ))?;
+    let ctx = Ctx::from_jwt(refresh_cookie.value(), &state.jwt_keys, TokenType::Refresh)?;
+    // each recovery code is an argon2 check, failures count like failed logins
+    let throttle_key = ctx.user_id.to_string();
+    let ip = client.ip_address.as_deref().and_then(|ip| ip.parse().ok());
+    state.login_throttle.check(&throttle_key, ip)?;
 
-    let totp_secret = sqlx::query_as!(
-        TotpSecret,
-        r#"SELECT totp_secret FROM  users
-        WHERE
-            id=$1"#,
-        ctx.user_id,
-    )
-    .fetch_one(&state.db_pool)
-    .await
-    .context("can't get totp")?;
+    let is_valid = totp::verify(&state, ctx.user_id, &secret.totp).await?;
+    // a recovery code works in place of a totp code
+    let is_valid = is_valid
+        || (recovery_codes::looks_like_recovery_code(&secret.totp)
+            && recovery_codes::redeem(&state.db_pool, ctx.user_id, &secret.totp).await?);
+    match is_valid {
+        true => {
+            state.login_throttle.record_success(&throttle_key);
+            let mut ctx = refresh_token::consume(&state, refresh_cookie.value()).await?;
+            let (mut ctx, refresh_jwt) =
+                refresh_token::issue(&state, ctx.with_valid_totp(true), &client).await?;
+            cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+            let auth_jwt = ctx.as_auth().to_jwt(&state.jwt_keys)?;
+            Ok((json!({"jwt":auth_jwt,"ctx":ctx}).to_string()).into_response())
+        }
+        false => {
+            state.login_throttle.record_failure(&throttle_key, ip);
+            Ok(StatusCode::BAD_REQUEST.into_response())
+        }
+    }
+}
 
-    match totp_rs::TOTP::new(
-        totp_rs::Algorithm::SHA1,
-        6,
-        1,
-        30,
-        totp_secret.totp_secret,
-        Some(state.app_name.clone()),
-        state.app_name.clone(),
-    )
-    .context("error creating totp url")?
-    .check(&secret.totp, Utc::now().timestamp() as u64)
-    {
+/// Replace the recovery codes, a fresh totp code is required.
+#[tracing::instrument(skip_all)]
+pub async fn post_recovery_codes(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(totp_request): Json<TotpRequest>,
+) -> Result<Response, GenericAuthError> {
+    let is_valid = totp::verify(&state, ctx.user_id, &totp_request.totp).await?;
+    match is_valid {
         true => {
-            let refresh_jwt = ctx
-                .with_valid_totp(true)
-                .to_jwt(EncodingKey::from_secret(state.auth_secret.as_bytes()))?;
-            refresh_cookie.set_value(refresh_jwt.clone());
-            Ok((json!({"jwt":refresh_jwt,"ctx":ctx}).to_string()).into_response())
+            let recovery_codes = recovery_codes::regenerate(&state.db_pool, ctx.user_id).await?;
+            Ok(json!({"recovery_codes":recovery_codes})
+                .to_string()
+                .into_response())
         }
         false => Ok(StatusCode::BAD_REQUEST.into_response()),
     }
@@ -0,0 +1,298 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{
+        ctx::{Ctx, TokenType},
+        error::AuthError,
+        refresh_token::{self, ClientInfo},
+        REFRESH_COOKIE,
+    },
+    controller::error::WebauthnError,
+    model::webauthn_credential::WebauthnCredential,
+};
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+const NAME_MAX_LEN: usize = 64;
+
+#[derive(serde::Deserialize)]
+pub struct NewPasskey {
+    name: String,
+}
+
+/// The second factor is checked against the refresh token, like [`super::totp::post`].
+fn refresh_ctx(state: &SharedAppState, cookies: &Cookies) -> Result<Ctx, AuthError> {
+    let refresh_cookie = cookies
+        .get(REFRESH_COOKIE)
+        .ok_or(AuthError::MissingCredentials)?;
+    Ok(Ctx::from_jwt(
+        refresh_cookie.value(),
+        &state.jwt_keys,
+        TokenType::Refresh,
+    )?)
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn get(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+) -> Result<Json<Vec<WebauthnCredential>>, WebauthnError> {
+    let credentials = WebauthnCredential::list_for(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't list passkeys")?;
+    Ok(Json(credentials))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, credential_id = %credential_id))]
+pub async fn delete_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(credential_id): Path<Uuid>,
+) -> Result<StatusCode, WebauthnError> {
+    let deleted = WebauthnCredential::delete(&state.db_pool, ctx.user_id, credential_id)
+        .await
+        .context("can't delete passkey")?;
+    match deleted {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(WebauthnError::NotFound),
+    }
+}
+
+/// Start registering a passkey, the challenge goes to `navigator.credentials.create()`.
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn post_register_start(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(new_passkey): Json<NewPasskey>,
+) -> Result<Json<CreationChallengeResponse>, WebauthnError> {
+    let name = new_passkey.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > NAME_MAX_LEN {
+        return Err(WebauthnError::InvalidName(NAME_MAX_LEN));
+    }
+    let user = sqlx::query!(
+        "SELECT username, full_name FROM users WHERE id=$1",
+        ctx.user_id
+    )
+    .fetch_one(&state.db_pool)
+    .await
+    .context("can't fetch user")?;
+    // the authenticator refuses to register twice
+    let registered = WebauthnCredential::passkeys_for(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't fetch passkeys")?
+        .into_iter()
+        .map(|(_, passkey)| passkey.cred_id().clone())
+        .collect();
+    let (challenge, registration) = state.webauthn.webauthn.start_passkey_registration(
+        ctx.user_id,
+        &user.username,
+        &user.full_name,
+        Some(registered),
+    )?;
+    state
+        .webauthn
+        .registrations
+        .insert(ctx.user_id, (name, registration));
+    Ok(Json(challenge))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn post_register_finish(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(credential): Json<RegisterPublicKeyCredential>,
+) -> Result<(StatusCode, Json<WebauthnCredential>), WebauthnError> {
+    let (name, registration) = state
+        .webauthn
+        .registrations
+        .take(&ctx.user_id)
+        .ok_or(WebauthnError::NoPendingCeremony)?;
+    let passkey = state
+        .webauthn
+        .webauthn
+        .finish_passkey_registration(&credential, &registration)?;
+    let credential = WebauthnCredential::insert(&state.db_pool, ctx.user_id, name, &passkey)
+        .await
+        .context("can't store passkey")?;
+    Ok((StatusCode::CREATED, Json(credential)))
+}
+
+/// Start a passkey check, the challenge goes to `navigator.credentials.get()`.
+#[tracing::instrument(skip_all)]
+pub async fn post_authenticate_start(
+    State(state): State<SharedAppState>,
+    cookies: Cookies,
+) -> Result<Json<RequestChallengeResponse>, WebauthnError> {
+    let ctx = refresh_ctx(&state, &cookies)?;
+    let passkeys = WebauthnCredential::passkeys_for(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't fetch passkeys")?
+        .into_iter()
+        .map(|(_, passkey)| passkey)
+        .collect::<Vec<_>>();
+    if passkeys.is_empty() {
+        return Err(WebauthnError::NoPasskeys);
+    }
+    let (challenge, authentication) = state
+        .webauthn
+        .webauthn
+        .start_passkey_authentication(&passkeys)?;
+    state
+        .webauthn
+        .authentications
+        .insert(ctx.user_id, authentication);
+    Ok(Json(challenge))
+}
+
+/// Finish a passkey check, it validates the second factor like a totp code.
+#[tracing::instrument(skip_all)]
+pub async fn post_authenticate_finish(
+    State(state): State<SharedAppState>,
+    cookies: Cookies,
+    client: ClientInfo,
+    Json(credential): Json<PublicKeyCredential>,
+) -> Result<Response, WebauthnError> {
+    let ctx = refresh_ctx(&state, &cookies)?;
+    let authentication = state
+        .webauthn
+        .authentications
+        .take(&ctx.user_id)
+        .ok_or(WebauthnError::NoPendingCeremony)?;
+    let result = state
+        .webauthn
+        .webauthn
+        .finish_passkey_authentication(&credential, &authentication)?;
+    let passkeys = WebauthnCredential::passkeys_for(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't fetch passkeys")?;
+    for (id, mut passkey) in passkeys {
+        // the signature counter guards against cloned authenticators
+        if passkey.update_credential(&result).is_some() {
+            WebauthnCredential::record_use(&state.db_pool, id, &passkey)
+                .await
+                .context("can't update passkey")?;
+        }
+    }
+
+    let refresh_cookie = cookies
+        .get(REFRESH_COOKIE)
+        .ok_or(AuthError::MissingCredentials)?;
+    let mut ctx = refresh_token::consume(&state, refresh_cookie.value()).await?;
+    let (mut ctx, refresh_jwt) =
+        refresh_token::issue(&state, ctx.with_valid_totp(true), &client).await?;
+    cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+    let auth_jwt = ctx
+        .as_auth()
+        .to_jwt(&state.jwt_keys)
+        .map_err(AuthError::from)?;
+    Ok((json!({"jwt":auth_jwt,"ctx":ctx}).to_string()).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::AppState, model::role::Role};
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+    use webauthn_rs::prelude::Url;
+
+    async fn insert_user(state: &AppState) -> Uuid {
+        let user_id = Uuid::now_v7();
+        sqlx::query!(
+            r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret, active)
+            VALUES ($1, 'user', 'alice', '', 'alice@example.com', 'Alice', x'00', 1)"#,
+            user_id,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        user_id
+    }
+
+    #[tokio::test]
+    async fn registers_then_authenticates_with_a_passkey() {
+        let state = AppState::for_tests().await;
+        let user_id = insert_user(&state).await;
+        let ctx = Ctx::new(user_id, vec![Role::User]);
+        let origin = Url::parse("http://localhost:5173").unwrap();
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let Json(challenge) = post_register_start(
+            State(state.clone()),
+            ctx.clone(),
+            Json(NewPasskey {
+                name: "laptop".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        let registration = authenticator
+            .do_registration(origin.clone(), challenge)
+            .unwrap();
+        let (status, _) = post_register_finish(
+            State(state.clone()),
+            ctx.clone(),
+            Json(registration.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(matches!(
+            post_register_finish(State(state.clone()), ctx.clone(), Json(registration)).await,
+            Err(WebauthnError::NoPendingCeremony)
+        ));
+
+        // the second factor is checked for the session of the refresh cookie
+        let (_, refresh_jwt) = refresh_token::issue(&state, &ctx, &ClientInfo::default())
+            .await
+            .unwrap();
+        let cookies = Cookies::default();
+        cookies.add(refresh_token::refresh_cookie(refresh_jwt));
+        let Json(challenge) = post_authenticate_start(State(state.clone()), cookies.clone())
+            .await
+            .unwrap();
+        let assertion = authenticator.do_authentication(origin, challenge).unwrap();
+        let response = post_authenticate_finish(
+            State(state.clone()),
+            cookies.clone(),
+            ClientInfo::default(),
+            Json(assertion.clone()),
+        )
+        .await
+        .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let auth_ctx = Ctx::from_jwt(
+            body["jwt"].as_str().unwrap(),
+            &state.jwt_keys,
+            TokenType::Access,
+        )
+        .unwrap();
+        assert!(auth_ctx.valid_totp);
+        assert_eq!(auth_ctx.user_id, user_id);
+
+        // a replayed assertion finds no pending ceremony
+        assert!(matches!(
+            post_authenticate_finish(
+                State(state.clone()),
+                cookies,
+                ClientInfo::default(),
+                Json(assertion),
+            )
+            .await,
+            Err(WebauthnError::NoPendingCeremony)
+        ));
+    }
+}
@@ -0,0 +1,636 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::ctx::Ctx,
+    controller::error::DeviceError,
+    middleware::mw_roles,
+    model::{
+        device::{Device, DeviceUpdate, NewDevice},
+        device_event::DeviceEvent,
+        role::Role,
+        session::Session,
+    },
+    probe::{self, ProbeResult, WaitOutcome},
+    wol::{self, error::WolError, Transport, WakeReport},
+};
+use anyhow::Context;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use chrono::Utc;
+use futures_util::stream::{self, Stream};
+use std::{collections::HashSet, net::IpAddr, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Instant, Interval};
+use uuid::Uuid;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PowerOnQuery {
+    /// Keep probing the device until it's up or the timeout expires.
+    #[serde(default)]
+    wait: bool,
+    /// Capped by `prober.wake_timeout_secs`.
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PowerOnResponse {
+    #[serde(flatten)]
+    report: WakeReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait: Option<WaitOutcome>,
+}
+
+fn map_write_error(error: sqlx::Error) -> DeviceError {
+    match error.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => DeviceError::AlreadyExists,
+        _ => DeviceError::UnexpectedError(anyhow::Error::new(error).context("can't write device")),
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), DeviceError> {
+    match name.trim().is_empty() {
+        true => Err(DeviceError::InvalidDevice("name can't be empty")),
+        false => Ok(()),
+    }
+}
+
+fn validate_network(
+    transport: Transport,
+    wol_port: Option<u16>,
+    interface: Option<&str>,
+) -> Result<(), DeviceError> {
+    if transport == Transport::Ethernet && interface.is_none() {
+        return Err(WolError::MissingInterface.into());
+    }
+    if wol_port == Some(0) {
+        return Err(DeviceError::InvalidDevice("wol_port can't be 0"));
+    }
+    if let Some(interface) = interface {
+        wol::validate_interface_name(interface)?;
+    }
+    Ok(())
+}
+
+/// Broadcast address of a local network: private, link-local or the limited broadcast.
+fn is_local_broadcast(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            address.is_private() || address.is_link_local() || address.is_broadcast()
+        }
+        IpAddr::V6(address) => {
+            address.is_unique_local()
+                || address.is_unicast_link_local()
+                || address.segments()[0] == 0xff02
+        }
+    }
+}
+
+/// Users can only send the packets to a local network, an admin picks any address and the interface,
+/// so the ethernet transport, which needs one.
+fn validate_overrides(
+    ctx: &Ctx,
+    broadcast_address: Option<IpAddr>,
+    interface: Option<&str>,
+) -> Result<(), DeviceError> {
+    if ctx.is_admin() {
+        return Ok(());
+    }
+    if interface.is_some() {
+        return Err(DeviceError::AdminOnly("the interface"));
+    }
+    match broadcast_address {
+        Some(address) if !is_local_broadcast(address) => Err(DeviceError::AdminOnly(
+            "a broadcast address outside the local networks",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Host of a local network, the server itself excluded.
+fn is_local_host(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => address.is_private() || address.is_link_local(),
+        IpAddr::V6(address) => address.is_unique_local() || address.is_unicast_link_local(),
+    }
+}
+
+/// The probe result is returned to the user, so users only probe hosts of a local network on the
+/// default port: else the server would scan any host and port for them. An admin picks anything.
+fn validate_probe_target(
+    ctx: &Ctx,
+    ip_address: Option<IpAddr>,
+    probe_port: Option<u16>,
+) -> Result<(), DeviceError> {
+    if ctx.is_admin() {
+        return Ok(());
+    }
+    if probe_port.is_some() {
+        return Err(DeviceError::AdminOnly("the probe port"));
+    }
+    match ip_address {
+        Some(address) if !is_local_host(address) => Err(DeviceError::AdminOnly(
+            "an ip address outside the local networks",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn validate_probe(probe_port: Option<u16>, probe_interval: Option<u32>) -> Result<(), DeviceError> {
+    if probe_port == Some(0) {
+        return Err(DeviceError::InvalidDevice("probe_port can't be 0"));
+    }
+    if probe_interval == Some(0) {
+        return Err(DeviceError::InvalidDevice("probe_interval can't be 0"));
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn get(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+) -> Result<Json<Vec<Device>>, DeviceError> {
+    let devices = Device::list_visible(&state.db_pool, &ctx)
+        .await
+        .context("can't list devices")?;
+    Ok(Json(devices))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn post(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(device): Json<NewDevice>,
+) -> Result<(StatusCode, Json<Device>), DeviceError> {
+    validate_name(&device.name)?;
+    validate_network(
+        device.transport,
+        device.wol_port,
+        device.interface.as_deref(),
+    )?;
+    validate_overrides(&ctx, device.broadcast_address, device.interface.as_deref())?;
+    validate_probe_target(&ctx, device.ip_address, device.probe_port)?;
+    validate_probe(device.probe_port, device.probe_interval)?;
+    let device_id = Uuid::now_v7();
+    let secure_on = device
+        .secure_on
+        .as_ref()
+        .map(|secure_on| state.secure_on_cipher.encrypt(device_id, secure_on))
+        .transpose()?;
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("can't start transaction")?;
+    let device = Device {
+        id: device_id,
+        mac_address: device.mac_address.to_string(),
+        name: device.name.trim().to_string(),
+        description: device.description,
+        on: false,
+        secure_on,
+        broadcast_address: device.broadcast_address.map(|address| address.to_string()),
+        wol_port: device.wol_port,
+        interface: device.interface,
+        transport: device.transport,
+        ip_address: device.ip_address.map(|address| address.to_string()),
+        probe_method: device.probe_method,
+        probe_port: device.probe_port,
+        probe_interval: device.probe_interval,
+        last_seen: None,
+        expected_wake_ms: None,
+    };
+    sqlx::query!(
+        r#"INSERT INTO devices(id, mac_address, name, description, secure_on, broadcast_address, wol_port, interface, transport,
+                ip_address, probe_method, probe_port, probe_interval)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"#,
+        device.id,
+        device.mac_address,
+        device.name,
+        device.description,
+        device.secure_on,
+        device.broadcast_address,
+        device.wol_port,
+        device.interface,
+        device.transport,
+        device.ip_address,
+        device.probe_method,
+        device.probe_port,
+        device.probe_interval,
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(map_write_error)?;
+
+    sqlx::query!(
+        "INSERT INTO user_devices(user_id, device_id, owner) VALUES ($1, $2, 1)",
+        ctx.user_id,
+        device.id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("can't assign device to user")?;
+
+    transaction
+        .commit()
+        .await
+        .context("can't commit transaction")?;
+    Ok((StatusCode::CREATED, Json(device)))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, device_id = %device_id))]
+pub async fn get_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(device_id): Path<Uuid>,
+) -> Result<Json<Device>, DeviceError> {
+    let device = Device::fetch_visible(&state.db_pool, &ctx, device_id)
+        .await
+        .context("can't fetch device")?
+        .ok_or(DeviceError::NotFound)?;
+    Ok(Json(device))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, device_id = %device_id))]
+pub async fn put_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(device_id): Path<Uuid>,
+    Json(update): Json<DeviceUpdate>,
+) -> Result<Json<Device>, DeviceError> {
+    let mut device = Device::fetch_visible(&state.db_pool, &ctx, device_id)
+        .await
+        .context("can't fetch device")?
+        .ok_or(DeviceError::NotFound)?;
+    // users the device is shared with can wake it, not reconfigure it
+    if !ctx.is_admin()
+        && !Device::is_owned(&state.db_pool, ctx.user_id, device_id)
+            .await
+            .context("can't check device ownership")?
+    {
+        return Err(DeviceError::NotOwner);
+    }
+    // only the changed values, an interface set by an admin doesn't lock the owner out
+    validate_overrides(
+        &ctx,
+        update.broadcast_address.flatten(),
+        update
+            .interface
+            .as_ref()
+            .and_then(|interface| interface.as_deref()),
+    )?;
+    validate_probe_target(
+        &ctx,
+        update.ip_address.flatten(),
+        update.probe_port.flatten(),
+    )?;
+    if let Some(mac_address) = update.mac_address {
+        device.mac_address = mac_address.to_string();
+    }
+    if let Some(name) = update.name {
+        validate_name(&name)?;
+        device.name = name.trim().to_string();
+    }
+    if let Some(description) = update.description {
+        device.description = Some(description);
+    }
+    if let Some(secure_on) = update.secure_on {
+        device.secure_on = secure_on
+            .as_ref()
+            .map(|secure_on| state.secure_on_cipher.encrypt(device.id, secure_on))
+            .transpose()?;
+    }
+    if let Some(broadcast_address) = update.broadcast_address {
+        device.broadcast_address = broadcast_address.map(|address| address.to_string());
+    }
+    if let Some(wol_port) = update.wol_port {
+        device.wol_port = wol_port;
+    }
+    if let Some(interface) = update.interface {
+        device.interface = interface;
+    }
+    if let Some(transport) = update.transport {
+        device.transport = transport;
+    }
+    if let Some(ip_address) = update.ip_address {
+        device.ip_address = ip_address.map(|address| address.to_string());
+    }
+    if let Some(probe_method) = update.probe_method {
+        device.probe_method = probe_method;
+    }
+    if let Some(probe_port) = update.probe_port {
+        device.probe_port = probe_port;
+    }
+    if let Some(probe_interval) = update.probe_interval {
+        device.probe_interval = probe_interval;
+    }
+    validate_network(
+        device.transport,
+        device.wol_port,
+        device.interface.as_deref(),
+    )?;
+    validate_probe(device.probe_port, device.probe_interval)?;
+
+    sqlx::query!(
+        r#"UPDATE devices
+        SET mac_address=$1, name=$2, description=$3, secure_on=$4,
+            broadcast_address=$5, wol_port=$6, interface=$7, transport=$8,
+            ip_address=$9, probe_method=$10, probe_port=$11, probe_interval=$12
+        WHERE id=$13"#,
+        device.mac_address,
+        device.name,
+        device.description,
+        device.secure_on,
+        device.broadcast_address,
+        device.wol_port,
+        device.interface,
+        device.transport,
+        device.ip_address,
+        device.probe_method,
+        device.probe_port,
+        device.probe_interval,
+        device.id,
+    )
+    .execute(&state.db_pool)
+    .await
+    .map_err(map_write_error)?;
+    Ok(Json(device))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, device_id = %device_id))]
+pub async fn delete_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(device_id): Path<Uuid>,
+) -> Result<StatusCode, DeviceError> {
+    let device = Device::fetch_visible(&state.db_pool, &ctx, device_id)
+        .await
+        .context("can't fetch device")?
+        .ok_or(DeviceError::NotFound)?;
+    let owned = Device::is_owned(&state.db_pool, ctx.user_id, device.id)
+        .await
+        .context("can't check device ownership")?;
+    if !ctx.is_admin() && !owned {
+        // a user the device is shared with only removes it from their own list
+        sqlx::query!(
+            "DELETE FROM user_devices WHERE device_id=$1 AND user_id=$2",
+            device.id,
+            ctx.user_id,
+        )
+        .execute(&state.db_pool)
+        .await
+        .context("can't unassign device")?;
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    // `user_devices` and `device_types` rows are removed by `ON DELETE CASCADE`
+    sqlx::query!("DELETE FROM devices WHERE id=$1", device.id)
+        .execute(&state.db_pool)
+        .await
+        .context("can't delete device")?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, device_id = %device_id))]
+pub async fn post_power_on_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(device_id): Path<Uuid>,
+    Query(query): Query<PowerOnQuery>,
+) -> Result<Json<PowerOnResponse>, DeviceError> {
+    let device = Device::fetch_visible(&state.db_pool, &ctx, device_id)
+        .await
+        .context("can't fetch device")?
+        .ok_or(DeviceError::NotFound)?;
+    // fail early on a bad network configuration, before probing
+    device.wake_target(&state.wol)?;
+
+    // probing first fails early on devices that can't be probed at all
+    let already_on = match query.wait {
+        true => {
+            let result = probe::probe_device(&device, &state.prober).await?;
+            probe::record(&state, &result)
+                .await
+                .context("can't record probe result")?;
+            result.on
+        }
+        false => false,
+    };
+    let report = device.power_on(&state).await?;
+    let sent_at = Instant::now();
+    let wait = match (query.wait, already_on) {
+        (false, _) => None,
+        (true, true) => Some(WaitOutcome::AlreadyOn),
+        (true, false) => {
+            let timeout = query
+                .timeout_secs
+                .unwrap_or(state.prober.wake_timeout_secs)
+                .min(state.prober.wake_timeout_secs);
+            let outcome =
+                probe::wait_until_online(&state, &device, sent_at, Duration::from_secs(timeout))
+                    .await?;
+            Some(outcome)
+        }
+    };
+    Ok(Json(PowerOnResponse { report, wait }))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, device_id = %device_id))]
+pub async fn get_refresh_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(device_id): Path<Uuid>,
+) -> Result<Json<ProbeResult>, DeviceError> {
+    let device = Device::fetch_visible(&state.db_pool, &ctx, device_id)
+        .await
+        .context("can't fetch device")?
+        .ok_or(DeviceError::NotFound)?;
+    let result = probe::probe_device(&device, &state.prober).await?;
+    probe::record(&state, &result)
+        .await
+        .context("can't record probe result")?;
+    Ok(Json(result))
+}
+
+/// How often an event stream re-checks its session and the devices it can see.
+const EVENTS_RECHECK: Duration = Duration::from_secs(30);
+
+/// Subscriber of [`get_events`].
+struct EventStream {
+    receiver: broadcast::Receiver<DeviceEvent>,
+    state: SharedAppState,
+    ctx: Ctx,
+    /// `None` for admins, they see every device.
+    visible: Option<HashSet<Uuid>>,
+    /// When the token the stream was opened with expires.
+    expires_at: Instant,
+    recheck: Interval,
+}
+
+impl EventStream {
+    /// Read again what the stream can see, `false` once the user is inactive or signed out.
+    async fn refresh(&mut self) -> Result<bool, anyhow::Error> {
+        let pool = &self.state.db_pool;
+        let active =
+            sqlx::query_scalar!("SELECT active FROM users WHERE id = $1", self.ctx.user_id)
+                .fetch_optional(pool)
+                .await
+                .context("can't fetch user active status")?
+                .unwrap_or(false);
+        if !active {
+            return Ok(false);
+        }
+        if let Some(session_id) = self.ctx.sid {
+            if !Session::is_active(pool, self.ctx.user_id, session_id)
+                .await
+                .context("can't fetch session status")?
+            {
+                return Ok(false);
+            }
+        }
+        let roles = mw_roles::fetch_roles(&self.state, self.ctx.user_id).await?;
+        self.visible = match roles.contains(&Role::Admin) {
+            true => None,
+            false => Some(
+                Device::visible_ids(pool, self.ctx.user_id)
+                    .await
+                    .context("can't list visible devices")?
+                    .into_iter()
+                    .collect(),
+            ),
+        };
+        Ok(true)
+    }
+
+    fn can_see(&self, device_id: Uuid) -> bool {
+        self.visible
+            .as_ref()
+            .is_none_or(|visible| visible.contains(&device_id))
+    }
+
+    /// Next event the stream can see, `None` when it's over.
+    async fn next(&mut self) -> Option<Result<Event, axum::Error>> {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(self.expires_at) => return None,
+                _ = self.recheck.tick() => match self.refresh().await {
+                    Ok(true) => {}
+                    Ok(false) => return None,
+                    Err(e) => tracing::error!("can't refresh device events stream: {:#}", e),
+                },
+                event = self.receiver.recv() => {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(skipped)) => {
+                            tracing::warn!("device events stream lagged, skipped {}", skipped);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    };
+                    if self.can_see(event.device_id) {
+                        return Some(Event::default().event(event.kind.as_str()).json_data(&event));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Stream state changes of the devices `ctx` can see as Server-Sent Events.
+///
+/// The stream ends when the token expires, the session is revoked or the user deactivated.
+/// What it can see is read again every [`EVENTS_RECHECK`].
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn get_events(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, DeviceError> {
+    let lifetime = Duration::from_secs((ctx.exp - Utc::now().timestamp()).max(0) as u64);
+    let mut events = EventStream {
+        receiver: state.device_events.subscribe(),
+        state,
+        ctx,
+        visible: None,
+        expires_at: Instant::now() + lifetime,
+        recheck: tokio::time::interval_at(Instant::now() + EVENTS_RECHECK, EVENTS_RECHECK),
+    };
+    let open = events.refresh().await?;
+    let events = stream::unfold(open.then_some(events), |events| async move {
+        let mut events = events?;
+        let event = events.next().await?;
+        Some((event, Some(events)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_only_target_local_networks() {
+        let user = Ctx::new(Uuid::now_v7(), vec![Role::User]);
+        for address in [
+            "192.168.20.255",
+            "10.255.255.255",
+            "172.16.0.255",
+            "169.254.255.255",
+            "255.255.255.255",
+            "ff02::1",
+            "fd00::ffff",
+        ] {
+            assert!(
+                validate_overrides(&user, address.parse().ok(), None).is_ok(),
+                "{address}"
+            );
+        }
+        for address in ["8.8.8.8", "203.0.113.255", "2001:db8::1"] {
+            assert!(
+                matches!(
+                    validate_overrides(&user, address.parse().ok(), None),
+                    Err(DeviceError::AdminOnly(_))
+                ),
+                "{address}"
+            );
+        }
+        assert!(validate_overrides(&user, None, Some("eth0")).is_err());
+        assert!(validate_overrides(&user, None, None).is_ok());
+    }
+
+    #[test]
+    fn users_only_probe_local_hosts_on_the_default_port() {
+        let user = Ctx::new(Uuid::now_v7(), vec![Role::User]);
+        for address in [
+            "192.168.20.12",
+            "10.0.0.2",
+            "169.254.1.1",
+            "fd00::12",
+            "fe80::1",
+        ] {
+            assert!(
+                validate_probe_target(&user, address.parse().ok(), None).is_ok(),
+                "{address}"
+            );
+        }
+        for address in ["8.8.8.8", "127.0.0.1", "0.0.0.0", "::1", "2001:db8::1"] {
+            assert!(
+                matches!(
+                    validate_probe_target(&user, address.parse().ok(), None),
+                    Err(DeviceError::AdminOnly(_))
+                ),
+                "{address}"
+            );
+        }
+        assert!(validate_probe_target(&user, None, Some(22)).is_err());
+        assert!(validate_probe_target(&user, None, None).is_ok());
+    }
+
+    #[test]
+    fn admins_override_anything() {
+        let admin = Ctx::new(Uuid::now_v7(), vec![Role::Admin]);
+        assert!(validate_overrides(&admin, "8.8.8.8".parse().ok(), Some("eth0")).is_ok());
+        assert!(validate_probe_target(&admin, "8.8.8.8".parse().ok(), Some(22)).is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::ctx::Ctx,
+    controller::error::ScheduleError,
+    model::{
+        device::Device,
+        schedule::{self, NewSchedule, Schedule, ScheduleRun, ScheduleUpdate},
+    },
+};
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use chrono_tz::Tz;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// How many runs `GET /api/schedules/{id}/runs` returns.
+const RUNS_LIMIT: u32 = 50;
+
+fn parse_timezone(timezone: &str) -> Result<Tz, ScheduleError> {
+    Tz::from_str(timezone.trim()).map_err(|_| ScheduleError::InvalidTimezone(timezone.to_string()))
+}
+
+/// Validate the schedule and plan its next run.
+fn plan(schedule: &mut Schedule) -> Result<(), ScheduleError> {
+    let cron = schedule::parse_cron(&schedule.cron)?;
+    let timezone = parse_timezone(&schedule.timezone)?;
+    schedule.cron = schedule.cron.trim().to_string();
+    schedule.timezone = timezone.name().to_string();
+    schedule.next_run = match schedule.enabled {
+        true => schedule::next_occurrence(&cron, timezone, Utc::now()),
+        false => None,
+    };
+    Ok(())
+}
+
+async fn fetch_schedule(
+    state: &SharedAppState,
+    ctx: &Ctx,
+    schedule_id: Uuid,
+) -> Result<Schedule, ScheduleError> {
+    Schedule::fetch_for(&state.db_pool, ctx, schedule_id)
+        .await
+        .context("can't fetch schedule")?
+        .ok_or(ScheduleError::NotFound)
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn get(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+) -> Result<Json<Vec<Schedule>>, ScheduleError> {
+    let schedules = Schedule::list_for(&state.db_pool, &ctx)
+        .await
+        .context("can't list schedules")?;
+    Ok(Json(schedules))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn post(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Json(schedule): Json<NewSchedule>,
+) -> Result<(StatusCode, Json<Schedule>), ScheduleError> {
+    // only the devices assigned to the user can be scheduled, admins included
+    let assigned = Device::is_assigned(&state.db_pool, ctx.user_id, schedule.device_id)
+        .await
+        .context("can't check device assignment")?;
+    if !assigned {
+        return Err(ScheduleError::DeviceNotFound);
+    }
+    let mut schedule = Schedule {
+        id: Uuid::now_v7(),
+        user_id: ctx.user_id,
+        device_id: schedule.device_id,
+        cron: schedule.cron,
+        timezone: schedule.timezone.unwrap_or_else(|| "UTC".to_string()),
+        enabled: schedule.enabled.unwrap_or(true),
+        next_run: None,
+        created_at: Utc::now(),
+    };
+    plan(&mut schedule)?;
+    sqlx::query!(
+        r#"INSERT INTO schedules(id, user_id, device_id, cron, timezone, enabled, next_run, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        schedule.id,
+        schedule.user_id,
+        schedule.device_id,
+        schedule.cron,
+        schedule.timezone,
+        schedule.enabled,
+        schedule.next_run,
+        schedule.created_at,
+    )
+    .execute(&state.db_pool)
+    .await
+    .context("can't create schedule")?;
+    Ok((StatusCode::CREATED, Json(schedule)))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, schedule_id = %schedule_id))]
+pub async fn get_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(schedule_id): Path<Uuid>,
+) -> Result<Json<Schedule>, ScheduleError> {
+    Ok(Json(fetch_schedule(&state, &ctx, schedule_id).await?))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, schedule_id = %schedule_id))]
+pub async fn put_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(schedule_id): Path<Uuid>,
+    Json(update): Json<ScheduleUpdate>,
+) -> Result<Json<Schedule>, ScheduleError> {
+    let mut schedule = fetch_schedule(&state, &ctx, schedule_id).await?;
+    if let Some(cron) = update.cron {
+        schedule.cron = cron;
+    }
+    if let Some(timezone) = update.timezone {
+        schedule.timezone = timezone;
+    }
+    if let Some(enabled) = update.enabled {
+        schedule.enabled = enabled;
+    }
+    plan(&mut schedule)?;
+    sqlx::query!(
+        r#"UPDATE schedules SET cron=$1, timezone=$2, enabled=$3, next_run=$4 WHERE id=$5"#,
+        schedule.cron,
+        schedule.timezone,
+        schedule.enabled,
+        schedule.next_run,
+        schedule.id,
+    )
+    .execute(&state.db_pool)
+    .await
+    .context("can't update schedule")?;
+    Ok(Json(schedule))
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, schedule_id = %schedule_id))]
+pub async fn delete_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(schedule_id): Path<Uuid>,
+) -> Result<StatusCode, ScheduleError> {
+    let schedule = fetch_schedule(&state, &ctx, schedule_id).await?;
+    // `schedule_runs` rows are removed by `ON DELETE CASCADE`
+    sqlx::query!("DELETE FROM schedules WHERE id=$1", schedule.id)
+        .execute(&state.db_pool)
+        .await
+        .context("can't delete schedule")?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, schedule_id = %schedule_id))]
+pub async fn get_runs_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(schedule_id): Path<Uuid>,
+) -> Result<Json<Vec<ScheduleRun>>, ScheduleError> {
+    let schedule = fetch_schedule(&state, &ctx, schedule_id).await?;
+    let runs = Schedule::list_runs(&state.db_pool, schedule.id, RUNS_LIMIT)
+        .await
+        .context("can't list schedule runs")?;
+    Ok(Json(runs))
+}
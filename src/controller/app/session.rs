@@ -0,0 +1,75 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::ctx::Ctx,
+    auth::refresh_token,
+    controller::error::SessionError,
+    model::{api_token::ApiToken, session::Session},
+};
+use anyhow::Context;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RevokeAllQuery {
+    /// Sign out everywhere else.
+    #[serde(default)]
+    keep_current: bool,
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn get(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+) -> Result<Json<Vec<Session>>, SessionError> {
+    let sessions = Session::list_active(&state.db_pool, ctx.user_id, ctx.sid)
+        .await
+        .context("can't list sessions")?;
+    Ok(Json(sessions))
+}
+
+/// Sign out everywhere, api tokens included, like an admin would.
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id))]
+pub async fn delete(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Query(query): Query<RevokeAllQuery>,
+) -> Result<StatusCode, SessionError> {
+    let except = ctx.sid.filter(|_| query.keep_current);
+    Session::revoke_all(&state.db_pool, ctx.user_id, except)
+        .await
+        .context("can't revoke sessions")?;
+    ApiToken::delete_all(&state.db_pool, ctx.user_id)
+        .await
+        .context("can't delete api tokens")?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(skip_all, fields(user_id = %ctx.user_id, session_id = %session_id))]
+pub async fn delete_by_id(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, SessionError> {
+    revoke(&state, ctx.user_id, session_id).await
+}
+
+pub(crate) async fn revoke(
+    state: &SharedAppState,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<StatusCode, SessionError> {
+    let active = Session::is_active(&state.db_pool, user_id, session_id)
+        .await
+        .context("can't fetch session")?;
+    if !active {
+        return Err(SessionError::NotFound);
+    }
+    refresh_token::revoke_session(&state.db_pool, session_id)
+        .await
+        .context("can't revoke session")?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,6 +1,10 @@
 use axum::{http::StatusCode, response::IntoResponse};
 
-use crate::auth::error::{AuthError, CtxError};
+use crate::{
+    auth::error::{AuthError, CtxError},
+    probe::error::ProbeError,
+    wol::error::WolError,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum GenericAuthError {
@@ -46,3 +50,252 @@ where
         Self(err.into())
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum DeviceError {
+    #[error("Device not found.")]
+    NotFound,
+    #[error("A device with this mac address already exists.")]
+    AlreadyExists,
+    #[error("Only the owner of the device or an admin can change it.")]
+    NotOwner,
+    #[error("Only an admin can set {0}.")]
+    AdminOnly(&'static str),
+    #[error("Invalid device: {0}")]
+    InvalidDevice(&'static str),
+    #[error(transparent)]
+    WolError(#[from] WolError),
+    #[error(transparent)]
+    ProbeError(#[from] ProbeError),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for DeviceError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            DeviceError::NotFound => StatusCode::NOT_FOUND.into_response(),
+            DeviceError::AlreadyExists => (StatusCode::CONFLICT, self.to_string()).into_response(),
+            DeviceError::NotOwner | DeviceError::AdminOnly(_) => {
+                (StatusCode::FORBIDDEN, self.to_string()).into_response()
+            }
+            DeviceError::InvalidDevice(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            DeviceError::WolError(wol_error) => wol_error.into_response(),
+            DeviceError::ProbeError(probe_error) => probe_error.into_response(),
+            DeviceError::AuthError(auth_error) => auth_error.into_response(),
+            DeviceError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScheduleError {
+    #[error("Schedule not found.")]
+    NotFound,
+    #[error("Device not found.")]
+    DeviceNotFound,
+    #[error("Invalid cron expression: {0}")]
+    InvalidCron(#[from] cron::error::Error),
+    #[error("Invalid time zone: {0}")]
+    InvalidTimezone(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for ScheduleError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            ScheduleError::NotFound | ScheduleError::DeviceNotFound => {
+                (StatusCode::NOT_FOUND, self.to_string()).into_response()
+            }
+            ScheduleError::InvalidCron(_) | ScheduleError::InvalidTimezone(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            ScheduleError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UserRequestError {
+    #[error("User request not found.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for UserRequestError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            UserRequestError::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            UserRequestError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SessionError {
+    #[error("Session not found.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for SessionError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            SessionError::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            SessionError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PasswordChangeError {
+    #[error("Wrong password.")]
+    WrongPassword,
+    #[error("The password must be at least 8 characters long, with lowercase, uppercase letters and digits.")]
+    WeakPassword,
+    #[error("The new password must be different from the old one.")]
+    SamePassword,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for PasswordChangeError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            PasswordChangeError::WrongPassword
+            | PasswordChangeError::WeakPassword
+            | PasswordChangeError::SamePassword => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            PasswordChangeError::AuthError(auth_error) => auth_error.into_response(),
+            PasswordChangeError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PasswordResetError {
+    #[error("Invalid or expired reset token.")]
+    InvalidToken,
+    #[error("The password must be at least 8 characters long, with lowercase, uppercase letters and digits.")]
+    WeakPassword,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for PasswordResetError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            PasswordResetError::InvalidToken | PasswordResetError::WeakPassword => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            PasswordResetError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiTokenError {
+    #[error("Api token not found.")]
+    NotFound,
+    #[error("The name must be between 1 and {0} characters long.")]
+    InvalidName(usize),
+    #[error("At least one scope is required.")]
+    MissingScopes,
+    #[error("The expiration must be between 1 and {0} days.")]
+    InvalidExpiration(u32),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for ApiTokenError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            ApiTokenError::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            ApiTokenError::InvalidName(_)
+            | ApiTokenError::MissingScopes
+            | ApiTokenError::InvalidExpiration(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            ApiTokenError::AuthError(auth_error) => auth_error.into_response(),
+            ApiTokenError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebauthnError {
+    #[error("Passkey not found.")]
+    NotFound,
+    #[error("The name must be between 1 and {0} characters long.")]
+    InvalidName(usize),
+    #[error("No passkey registered.")]
+    NoPasskeys,
+    #[error("No pending passkey ceremony, start a new one.")]
+    NoPendingCeremony,
+    #[error("Passkey rejected: {0}")]
+    Rejected(#[from] webauthn_rs::prelude::WebauthnError),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for WebauthnError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            WebauthnError::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            WebauthnError::InvalidName(_)
+            | WebauthnError::NoPasskeys
+            | WebauthnError::NoPendingCeremony
+            | WebauthnError::Rejected(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            WebauthnError::AuthError(auth_error) => auth_error.into_response(),
+            WebauthnError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+use crate::app_state::SharedAppState;
+use axum::{extract::State, Json};
+use jsonwebtoken::jwk::JwkSet;
+
+/// Public keys of the jwts, empty while they're signed with `auth_secret`.
+pub async fn get(State(state): State<SharedAppState>) -> Json<JwkSet> {
+    Json(state.jwt_keys.jwks())
+}
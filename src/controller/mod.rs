@@ -2,3 +2,4 @@ pub mod admin;
 pub mod app;
 pub mod error;
 pub mod health_check;
+pub mod jwks;
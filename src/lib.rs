@@ -2,7 +2,11 @@ pub mod app_state;
 pub mod auth;
 pub mod configuration;
 pub mod controller;
+pub mod mail;
 pub mod middleware;
 pub mod migration;
 pub mod model;
+pub mod probe;
+pub mod scheduler;
 pub mod telemetry;
+pub mod wol;
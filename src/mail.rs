@@ -0,0 +1,104 @@
+use crate::configuration::{MailBackend, MailSettings, SmtpTls};
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum MailError {
+    #[error("Invalid mail address: {0}")]
+    InvalidAddress(#[from] lettre::address::AddressError),
+    #[error("Can't build mail: {0}")]
+    InvalidMessage(#[from] lettre::error::Error),
+    #[error("Can't send mail: {0}")]
+    SmtpError(#[from] lettre::transport::smtp::Error),
+    #[error("Can't write mail: {0}")]
+    FileError(#[from] lettre::transport::file::Error),
+    #[error("Can't set up mail directory: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+enum MailTransport {
+    /// Only logs the mails, local development only: the body may hold secrets.
+    Log,
+    /// One `.eml` file per mail.
+    File(AsyncFileTransport<Tokio1Executor>),
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+}
+
+pub struct Mailer {
+    from: Mailbox,
+    transport: MailTransport,
+}
+
+impl Mailer {
+    pub fn from_settings(settings: &MailSettings) -> Result<Self, MailError> {
+        let transport = match &settings.backend {
+            MailBackend::Log => MailTransport::Log,
+            MailBackend::File { directory } => {
+                std::fs::create_dir_all(directory)?;
+                MailTransport::File(AsyncFileTransport::new(directory))
+            }
+            MailBackend::Smtp(smtp) => {
+                let builder = match smtp.tls {
+                    SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)?,
+                    SmtpTls::Starttls => {
+                        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)?
+                    }
+                    SmtpTls::None => {
+                        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host)
+                    }
+                };
+                let builder = builder.port(smtp.port);
+                let builder = match (&smtp.username, &smtp.password) {
+                    (Some(username), Some(password)) => {
+                        builder.credentials(Credentials::new(username.clone(), password.clone()))
+                    }
+                    _ => builder,
+                };
+                MailTransport::Smtp(builder.build())
+            }
+        };
+        Ok(Self {
+            from: settings.from.parse()?,
+            transport,
+        })
+    }
+
+    #[tracing::instrument(name = "send_mail", skip_all, fields(subject = %email.subject))]
+    pub async fn send(&self, email: Email) -> Result<(), MailError> {
+        if let MailTransport::Log = self.transport {
+            tracing::info!(
+                "mail to {}, subject {:?}:\n{}",
+                email.to,
+                email.subject,
+                email.body
+            );
+            return Ok(());
+        }
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)?;
+        match &self.transport {
+            MailTransport::Log => {}
+            MailTransport::File(transport) => {
+                transport.send(message).await?;
+            }
+            MailTransport::Smtp(transport) => {
+                transport.send(message).await?;
+            }
+        }
+        Ok(())
+    }
+}
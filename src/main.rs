@@ -2,23 +2,42 @@ use axum::{
     http::{self, header, HeaderValue, StatusCode, Uri},
     middleware,
     response::{Html, IntoResponse as _, Response},
-    routing::{get, post},
+    routing::{delete, get, post},
     serve, Router,
 };
 use sqlx::sqlite::SqlitePoolOptions;
 use std::net::SocketAddr;
+use tokio::sync::broadcast;
 use tower_cookies::CookieManagerLayer;
 use tower_http::cors;
 use wol_server::{
     app_state::{AppState, SharedAppState},
+    auth::{
+        jwt_keys::JwtKeys,
+        ldap::LdapBackend,
+        login_throttle::LoginThrottle,
+        oidc::OidcClient,
+        password::{CredentialsBackend, LocalBackend},
+        webauthn::WebauthnState,
+    },
     configuration::load_settings,
-    controller::{app, health_check},
-    middleware::mw_auth,
+    controller::{admin, app, health_check, jwks},
+    mail::Mailer,
+    middleware::{
+        mw_auth,
+        mw_rate_limit::{self, RateLimit},
+        mw_roles::{self, RequiredRoles},
+    },
     migration::db_migration,
+    model::role::Role,
+    probe::spawn_prober,
+    scheduler::spawn_scheduler,
     telemetry::{get_subscriber, init_subscriber},
+    wol::secure_on::SecureOnCipher,
 };
 
 const INDEX_HTML: &str = "index.html";
+const DEVICE_EVENTS_CAPACITY: usize = 256;
 
 #[derive(rust_embed::Embed)]
 #[folder = "frontend/dist"]
@@ -71,50 +90,234 @@ async fn main() {
     }
     let db_pool = SqlitePoolOptions::new().connect_lazy_with(settings.database.on_file());
     db_migration(&db_pool).await.expect("can't run migrations");
+    let secure_on_cipher = SecureOnCipher::from_secret(&settings.wol.secure_on_secret)
+        .expect("can't derive SecureOn key");
+    let webauthn = WebauthnState::from_settings(&settings.webauthn, &settings.application.app_name)
+        .expect("can't set up webauthn");
+    let jwt_keys = JwtKeys::from_settings(settings.jwt.as_ref(), &settings.application.auth_secret)
+        .expect("can't load jwt keys");
     let app_state = SharedAppState::new(AppState {
         base_url: settings.application.base_url,
         db_pool,
-        auth_secret: settings.application.auth_secret,
+        jwt_keys,
         app_name: settings.application.app_name,
+        totp: settings.totp,
+        wol: settings.wol,
+        secure_on_cipher,
+        prober: settings.prober,
+        mailer: Mailer::from_settings(&settings.mail).expect("can't set up mailer"),
+        password_reset: settings.password_reset,
+        login_throttle: LoginThrottle::new(settings.login_throttle),
+        credentials_backend: match settings.ldap {
+            Some(ldap) => Box::new(LdapBackend::new(ldap).expect("can't set up ldap"))
+                as Box<dyn CredentialsBackend>,
+            None => Box::new(LocalBackend),
+        },
+        webauthn,
+        oidc: settings
+            .oidc
+            .map(|oidc| OidcClient::new(oidc).expect("can't set up oidc")),
+        device_events: broadcast::channel(DEVICE_EVENTS_CAPACITY).0,
     });
+    if app_state.prober.enabled {
+        spawn_prober(app_state.clone());
+    }
+    if settings.scheduler.enabled {
+        spawn_scheduler(app_state.clone());
+    }
 
     // let serve_dir = ServeDir::new("frontend/dist");
     let serve_dir = get(static_handler);
 
     let app = Router::new()
-        // .route("/api/admin/user_requests", get(admin::get_user_requests)) // TODO: add pagination
-        // .route("/api/admin/user_requests/{id}", get(admin::get_user_request_by_id))
-        // .route("/api/admin/user_requests/{id}/reject", post(admin::post_accept_user_requests))
-        // .route("/api/admin/user_requests/{id}/accept", post(admin::post_reject_user_requests))
-        // .route("/api/devices", get(device::get)) // TODO: add pagination
-        // .route("/api/devices", post(device::post))
-        // .route("/api/devices/{id}", post(device::get_by_id))
-        // .route("/api/devices/{id}", delete(device::delete_by_id))
-        // .route("/api/devices/{id}/refresh", get(device::get_refresh_by_id)) // TODO: add rate limiting
-        // .route("/api/devices/{id}/power_on", post(device::post_power_on_by_id))
+        .nest(
+            "/api/admin",
+            Router::new()
+                .route("/user_requests", get(admin::user_requests::get))
+                .route("/user_requests/{id}", get(admin::user_requests::get_by_id))
+                .route(
+                    "/user_requests/{id}/accept",
+                    post(admin::user_requests::post_accept),
+                )
+                .route(
+                    "/user_requests/{id}/reject",
+                    post(admin::user_requests::post_reject),
+                )
+                .route(
+                    "/users/{id}/sessions",
+                    get(admin::sessions::get).delete(admin::sessions::delete),
+                )
+                .route(
+                    "/users/{id}/sessions/{session_id}",
+                    delete(admin::sessions::delete_by_id),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    RequiredRoles::new(app_state.clone(), [Role::Admin]),
+                    mw_roles::require_roles,
+                )),
+        )
+        .route(
+            "/api/devices",
+            get(app::device::get).post(app::device::post),
+        )
+        .route("/api/devices/events", get(app::device::get_events))
+        .route(
+            "/api/devices/{id}",
+            get(app::device::get_by_id)
+                .put(app::device::put_by_id)
+                .delete(app::device::delete_by_id),
+        )
+        .route(
+            "/api/devices/{id}/refresh",
+            get(app::device::get_refresh_by_id).route_layer(middleware::from_fn_with_state(
+                RateLimit::new(app_state.clone(), &settings.rate_limit.device_refresh),
+                mw_rate_limit::rate_limit,
+            )),
+        )
+        .route(
+            "/api/devices/{id}/power_on",
+            post(app::device::post_power_on_by_id).route_layer(middleware::from_fn_with_state(
+                RateLimit::new(app_state.clone(), &settings.rate_limit.power_on),
+                mw_rate_limit::rate_limit,
+            )),
+        )
+        .route(
+            "/api/schedules",
+            get(app::schedule::get).post(app::schedule::post),
+        )
+        .route(
+            "/api/schedules/{id}",
+            get(app::schedule::get_by_id)
+                .put(app::schedule::put_by_id)
+                .delete(app::schedule::delete_by_id),
+        )
+        .route(
+            "/api/schedules/{id}/runs",
+            get(app::schedule::get_runs_by_id),
+        )
+        // admins see every device and schedule, check the role against the database
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            mw_roles::resolve_roles,
+        ))
+        .route(
+            "/api/profile/api_tokens",
+            get(app::api_token::get).post(app::api_token::post),
+        )
+        .route(
+            "/api/profile/api_tokens/{id}",
+            delete(app::api_token::delete_by_id),
+        )
+        .route(
+            "/api/sessions",
+            get(app::session::get).delete(app::session::delete),
+        )
+        .route("/api/sessions/{id}", delete(app::session::delete_by_id))
         .route(
             "/api/auth/totp/regenerate",
             get(app::auth::totp::get_regenerate),
         )
+        .route(
+            "/api/auth/totp/recovery_codes",
+            post(app::auth::totp::post_recovery_codes),
+        )
         .route(
             "/api/auth/totp/validate",
             post(app::auth::totp::post_validate),
         )
-        .route("/api/auth/totp", post(app::auth::totp::post))
+        .route("/api/auth/webauthn", get(app::auth::webauthn::get))
+        .route(
+            "/api/auth/webauthn/{id}",
+            delete(app::auth::webauthn::delete_by_id),
+        )
+        .route(
+            "/api/auth/webauthn/register/start",
+            post(app::auth::webauthn::post_register_start),
+        )
+        .route(
+            "/api/auth/webauthn/register/finish",
+            post(app::auth::webauthn::post_register_finish),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            mw_auth::password_must_not_need_reset,
+        ))
+        .route("/api/auth/password", post(app::auth::password::post))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            mw_auth::totp_must_be_valid,
+        ))
+        .route(
+            "/api/auth/totp",
+            post(app::auth::totp::post).route_layer(middleware::from_fn_with_state(
+                RateLimit::new(app_state.clone(), &settings.rate_limit.login),
+                mw_rate_limit::rate_limit,
+            )),
+        )
+        .route(
+            "/api/auth/webauthn/authenticate/start",
+            post(app::auth::webauthn::post_authenticate_start),
+        )
+        .route(
+            "/api/auth/webauthn/authenticate/finish",
+            post(app::auth::webauthn::post_authenticate_finish),
+        )
         .route_layer(middleware::from_fn_with_state(
             app_state.clone(),
             mw_auth::user_must_be_active,
         ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            mw_auth::api_token_must_have_scope,
+        ))
         .route("/api/auth/signup", post(app::auth::signup::post))
         .route("/api/auth/refresh", get(app::auth::refresh::get))
         .route("/api/auth/logout", post(app::auth::logout::post))
-        .route("/api/auth/login", post(app::auth::login::post))
+        .route("/api/auth/oidc", get(app::auth::oidc::get))
+        .route(
+            "/api/auth/oidc/login",
+            get(app::auth::oidc::get_login).route_layer(middleware::from_fn_with_state(
+                RateLimit::new(app_state.clone(), &settings.rate_limit.login),
+                mw_rate_limit::rate_limit,
+            )),
+        )
+        .route(
+            "/api/auth/oidc/callback",
+            get(app::auth::oidc::get_callback),
+        )
+        .route(
+            "/api/auth/password/forgot",
+            post(app::auth::password_reset::post_forgot).route_layer(
+                middleware::from_fn_with_state(
+                    RateLimit::new(app_state.clone(), &settings.rate_limit.password_reset),
+                    mw_rate_limit::rate_limit,
+                ),
+            ),
+        )
+        .route(
+            "/api/auth/password/reset",
+            post(app::auth::password_reset::post_reset),
+        )
+        .route(
+            "/api/auth/login",
+            post(app::auth::login::post).route_layer(middleware::from_fn_with_state(
+                RateLimit::new(app_state.clone(), &settings.rate_limit.login),
+                mw_rate_limit::rate_limit,
+            )),
+        )
         .layer(CookieManagerLayer::new())
         .route("/api/health_check", get(health_check::get))
+        .route("/.well-known/jwks.json", get(jwks::get))
         .layer(
             cors::CorsLayer::new()
                 .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_methods([http::Method::GET, http::Method::POST, http::Method::OPTIONS])
+                .allow_methods([
+                    http::Method::GET,
+                    http::Method::POST,
+                    http::Method::PUT,
+                    http::Method::DELETE,
+                    http::Method::OPTIONS,
+                ])
                 .allow_credentials(true)
                 .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]),
         )
@@ -1,14 +1,15 @@
 use anyhow::Context;
 use axum::{
     body::Body,
-    extract::{Request, State},
+    extract::{MatchedPath, Request, State},
     middleware::Next,
     response::Response,
 };
 
 use crate::{
     app_state::SharedAppState,
-    auth::{ctx::Ctx, error::AuthError},
+    auth::{api_token, ctx::Ctx, error::AuthError},
+    model::session::Session,
 };
 
 pub async fn user_must_be_active(
@@ -23,8 +24,87 @@ pub async fn user_must_be_active(
         .context("Can't fetch user active status.")?
         .ok_or(AuthError::InactiveUser)?
         .active;
-    match is_active {
-        true => Ok(next.run(req).await),
-        false => Err(AuthError::InactiveUser),
+    if !is_active {
+        return Err(AuthError::InactiveUser);
+    }
+    // auth tokens outlive the sign-out of their session, at most by an hour
+    if let Some(session_id) = ctx.sid {
+        let session_active = Session::is_active(&state.db_pool, ctx.user_id, session_id)
+            .await
+            .context("Can't fetch session status.")?;
+        if !session_active {
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "Session revoked."
+            )));
+        }
+    }
+    Ok(next.run(req).await)
+}
+
+/// While `force_password_reset` is set only the password change is allowed.
+pub async fn password_must_not_need_reset(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let force_password_reset = sqlx::query_scalar!(
+        "SELECT force_password_reset FROM users WHERE id = $1",
+        ctx.user_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    .context("Can't fetch user password reset status.")?
+    .ok_or(AuthError::InactiveUser)?;
+    match force_password_reset {
+        true => Err(AuthError::PasswordResetRequired),
+        false => Ok(next.run(req).await),
+    }
+}
+
+/// Users with an enrolled authenticator or a passkey need a second factor validated token.
+///
+/// Routes added after this layer opt out, like the second factor checks themselves.
+pub async fn totp_must_be_valid(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    if ctx.valid_totp {
+        return Ok(next.run(req).await);
+    }
+    let has_second_factor = sqlx::query_scalar!(
+        r#"SELECT totp_enrolled OR EXISTS (
+            SELECT 1 FROM webauthn_credentials WHERE user_id = users.id
+        ) as "has_second_factor!: bool"
+        FROM users WHERE id = $1"#,
+        ctx.user_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    .context("Can't fetch user totp status.")?
+    .ok_or(AuthError::InactiveUser)?;
+    match has_second_factor {
+        true => Err(AuthError::InvalidTotp),
+        false => Ok(next.run(req).await),
+    }
+}
+
+/// API tokens only reach the routes their scopes allow, see [`api_token::required_scope`].
+pub async fn api_token_must_have_scope(
+    ctx: Ctx,
+    matched_path: Option<MatchedPath>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let Some(scopes) = &ctx.scopes else {
+        return Ok(next.run(req).await);
+    };
+    let required_scope =
+        matched_path.and_then(|path| api_token::required_scope(req.method(), path.as_str()));
+    match required_scope {
+        Some(scope) if scopes.contains(&scope) => Ok(next.run(req).await),
+        _ => Err(AuthError::InsufficientScope),
     }
 }
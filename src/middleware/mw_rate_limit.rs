@@ -0,0 +1,232 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{ctx::Ctx, error::AuthError},
+    configuration::BucketSettings,
+};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, FromRef, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// How often full buckets are dropped from memory.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RateLimitKey {
+    User(Uuid),
+    Ip(IpAddr),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<RateLimitKey, Bucket>,
+    pruned: Instant,
+}
+
+/// In-process token bucket, one bucket per user or client ip.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    // tokens per second
+    refill_rate: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &BucketSettings) -> Self {
+        Self {
+            capacity: settings.capacity as f64,
+            refill_rate: settings.per_minute as f64 / 60.0,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token for `key`, or tell how long until one is available.
+    fn acquire(&self, key: RateLimitKey) -> Result<(), Duration> {
+        self.acquire_at(key, Instant::now())
+    }
+
+    fn acquire_at(&self, key: RateLimitKey, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(buckets.pruned) > PRUNE_INTERVAL {
+            let (capacity, refill_rate) = (self.capacity, self.refill_rate);
+            buckets.buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * refill_rate
+                    < capacity
+            });
+            buckets.pruned = now;
+        }
+
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_rate).min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        match self.refill_rate > 0.0 {
+            true => Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_rate,
+            )),
+            false => Err(Duration::MAX),
+        }
+    }
+}
+
+/// State of the [`rate_limit`] middleware, one per limited route.
+#[derive(Clone)]
+pub struct RateLimit {
+    app_state: SharedAppState,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(app_state: SharedAppState, settings: &BucketSettings) -> Self {
+        Self {
+            app_state,
+            limiter: Arc::new(RateLimiter::new(settings)),
+        }
+    }
+}
+
+impl FromRef<RateLimit> for SharedAppState {
+    fn from_ref(input: &RateLimit) -> Self {
+        input.app_state.clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    retry_after: Duration,
+}
+
+impl IntoResponse for RateLimited {
+    fn into_response(self) -> Response {
+        let retry_after_secs = self.retry_after.as_secs_f64().ceil().min(u32::MAX as f64) as u64;
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after_secs.max(1).to_string())],
+            "Too many requests.",
+        )
+            .into_response()
+    }
+}
+
+/// Limit requests per authenticated user, or per client ip for anonymous requests.
+pub async fn rate_limit(
+    State(rate_limit): State<RateLimit>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    ctx: Result<Ctx, AuthError>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, RateLimited> {
+    // invalid tokens are the handler's business, they're limited by ip meanwhile
+    let key = match ctx {
+        Ok(ctx) => RateLimitKey::User(ctx.user_id),
+        Err(_) => RateLimitKey::Ip(client.ip()),
+    };
+    match rate_limit.limiter.acquire(key) {
+        Ok(()) => Ok(next.run(req).await),
+        Err(retry_after) => {
+            tracing::info!("rate limited {:?}", key);
+            Err(RateLimited { retry_after })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: RateLimitKey = RateLimitKey::Ip(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+
+    fn limiter(capacity: u32, per_minute: u32) -> RateLimiter {
+        RateLimiter::new(&BucketSettings {
+            capacity,
+            per_minute,
+        })
+    }
+
+    #[test]
+    fn a_burst_empties_the_bucket() {
+        let limiter = limiter(3, 60);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire_at(KEY, now), Ok(()));
+        }
+        assert_eq!(limiter.acquire_at(KEY, now), Err(Duration::from_secs(1)));
+        // other keys have their own bucket
+        let other = RateLimitKey::User(Uuid::now_v7());
+        assert_eq!(limiter.acquire_at(other, now), Ok(()));
+    }
+
+    #[test]
+    fn tokens_come_back_over_time_up_to_the_capacity() {
+        let limiter = limiter(3, 60);
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire_at(KEY, now).unwrap();
+        }
+        let later = now + Duration::from_millis(1500);
+        assert_eq!(limiter.acquire_at(KEY, later), Ok(()));
+        assert_eq!(
+            limiter.acquire_at(KEY, later),
+            Err(Duration::from_millis(500))
+        );
+
+        let much_later = later + Duration::from_secs(600);
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire_at(KEY, much_later), Ok(()));
+        }
+        assert!(limiter.acquire_at(KEY, much_later).is_err());
+    }
+
+    #[test]
+    fn a_bucket_without_refill_stays_empty() {
+        let limiter = limiter(1, 0);
+        let now = Instant::now();
+        limiter.acquire_at(KEY, now).unwrap();
+        assert_eq!(limiter.acquire_at(KEY, now), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up_to_whole_seconds() {
+        let retry_after = |retry_after| {
+            RateLimited { retry_after }
+                .into_response()
+                .headers()
+                .get(header::RETRY_AFTER)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(retry_after(Duration::from_millis(1200)), "2");
+        assert_eq!(retry_after(Duration::from_secs(3)), "3");
+        assert_eq!(retry_after(Duration::ZERO), "1");
+        assert_eq!(retry_after(Duration::MAX), u32::MAX.to_string());
+    }
+}
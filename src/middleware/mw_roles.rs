@@ -0,0 +1,127 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::{ctx::Ctx, error::AuthError},
+    model::role::Role,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{FromRef, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+/// State of the [`require_roles`] middleware: the roles every request must have.
+#[derive(Clone)]
+pub struct RequiredRoles {
+    app_state: SharedAppState,
+    roles: Arc<[Role]>,
+}
+
+impl RequiredRoles {
+    pub fn new(app_state: SharedAppState, roles: impl Into<Arc<[Role]>>) -> Self {
+        Self {
+            app_state,
+            roles: roles.into(),
+        }
+    }
+}
+
+/// Roles of the user read from the database by [`resolve_roles`] or [`require_roles`].
+///
+/// The [`Ctx`] extractor takes them over the ones in the jwt.
+#[derive(Clone, Debug)]
+pub struct ResolvedRoles(pub Vec<Role>);
+
+impl FromRef<RequiredRoles> for SharedAppState {
+    fn from_ref(input: &RequiredRoles) -> Self {
+        input.app_state.clone()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RoleError {
+    #[error("Missing roles.")]
+    MissingRoles(Vec<Role>),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+}
+
+impl IntoResponse for RoleError {
+    fn into_response(self) -> Response {
+        match self {
+            RoleError::MissingRoles(missing) => (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": "missing_roles",
+                    "missing": missing.iter().map(Role::to_string).collect::<Vec<_>>(),
+                })),
+            )
+                .into_response(),
+            RoleError::AuthError(auth_error) => auth_error.into_response(),
+        }
+    }
+}
+
+/// Roles are read again from the database, the ones in the jwt may be stale
+/// (e.g. an admin demoted after the token was issued).
+pub(crate) async fn fetch_roles(
+    state: &SharedAppState,
+    user_id: uuid::Uuid,
+) -> Result<Vec<Role>, AuthError> {
+    let roles = sqlx::query_scalar!("SELECT roles FROM users WHERE id = $1", user_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .context("Can't fetch user roles.")
+        .map_err(AuthError::from)?
+        .ok_or(AuthError::InactiveUser)?;
+    Role::parse_roles(&roles)
+        .map_err(|e| AuthError::UnexpectedError(anyhow::anyhow!("invalid stored roles: {}", e)))
+}
+
+/// Read the roles of the user from the database once for the request, see [`ResolvedRoles`].
+pub async fn resolve_roles(
+    State(state): State<SharedAppState>,
+    ctx: Ctx,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    if req.extensions().get::<ResolvedRoles>().is_none() {
+        let roles = fetch_roles(&state, ctx.user_id).await?;
+        req.extensions_mut().insert(ResolvedRoles(roles));
+    }
+    Ok(next.run(req).await)
+}
+
+/// Let the request through only if the user has every required role, see [`fetch_roles`].
+pub async fn require_roles(
+    State(required): State<RequiredRoles>,
+    ctx: Ctx,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, RoleError> {
+    let roles = match req.extensions().get::<ResolvedRoles>() {
+        Some(ResolvedRoles(roles)) => roles.clone(),
+        None => {
+            let roles = fetch_roles(&required.app_state, ctx.user_id).await?;
+            req.extensions_mut().insert(ResolvedRoles(roles.clone()));
+            roles
+        }
+    };
+    let missing = required
+        .roles
+        .iter()
+        .filter(|role| !roles.contains(role))
+        .cloned()
+        .collect::<Vec<_>>();
+    match missing.is_empty() {
+        true => Ok(next.run(req).await),
+        false => {
+            tracing::info!("user {} is missing roles {:?}", ctx.user_id, missing);
+            Err(RoleError::MissingRoles(missing))
+        }
+    }
+}
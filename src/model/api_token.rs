@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// What an API token may do, stored `|` separated like the user roles.
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+pub enum ApiScope {
+    #[serde(rename = "devices:read")]
+    DevicesRead,
+    #[serde(rename = "devices:wake")]
+    DevicesWake,
+}
+
+impl ApiScope {
+    /// Unknown scopes are skipped: they were removed since the token was created.
+    pub fn parse_scopes(scopes: &str) -> Vec<Self> {
+        scopes
+            .split("|")
+            .filter_map(|scope| ApiScope::try_from(scope).ok())
+            .collect()
+    }
+
+    pub fn join(scopes: &[Self]) -> String {
+        let mut scopes = scopes.iter().map(|x| (*x).into()).collect::<Vec<&str>>();
+        scopes.sort();
+        scopes.dedup();
+        scopes.join("|")
+    }
+}
+
+impl Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl TryFrom<&str> for ApiScope {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "devices:read" => Ok(ApiScope::DevicesRead),
+            "devices:wake" => Ok(ApiScope::DevicesWake),
+            _ => Err("invalid scope"),
+        }
+    }
+}
+
+impl From<ApiScope> for &'static str {
+    fn from(val: ApiScope) -> Self {
+        match val {
+            ApiScope::DevicesRead => "devices:read",
+            ApiScope::DevicesWake => "devices:wake",
+        }
+    }
+}
+
+/// A named token a user created for scripts, the secret itself is never stored.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NewApiToken {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub expires_in_days: Option<u32>,
+}
+
+impl ApiToken {
+    /// Tokens of `user_id`, expired ones included, newest first.
+    pub async fn list_for(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT id as "id: Uuid", user_id as "user_id: Uuid", name, scopes,
+                created_at as "created_at: DateTime<Utc>", expires_at as "expires_at: DateTime<Utc>",
+                last_used_at as "last_used_at: DateTime<Utc>"
+            FROM api_tokens
+            WHERE user_id = $1
+            ORDER BY id DESC"#,
+            user_id,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| ApiToken {
+                id: row.id,
+                user_id: row.user_id,
+                name: row.name,
+                scopes: ApiScope::parse_scopes(&row.scopes),
+                created_at: row.created_at,
+                expires_at: row.expires_at,
+                last_used_at: row.last_used_at,
+            })
+            .collect())
+    }
+
+    pub async fn insert(&self, pool: &SqlitePool, token_hash: Vec<u8>) -> Result<(), sqlx::Error> {
+        let scopes = ApiScope::join(&self.scopes);
+        sqlx::query!(
+            r#"INSERT INTO api_tokens(id, user_id, name, token_hash, scopes, created_at, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+            self.id,
+            self.user_id,
+            self.name,
+            token_hash,
+            scopes,
+            self.created_at,
+            self.expires_at,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete a token of `user_id`, `false` when there's no such token.
+    pub async fn delete(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        token_id: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM api_tokens WHERE id=$1 AND user_id=$2",
+            token_id,
+            user_id,
+        )
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Delete every token of `user_id`, when its sessions are all signed out.
+    pub async fn delete_all(pool: &SqlitePool, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        sqlx::query!("DELETE FROM api_tokens WHERE user_id=$1", user_id)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_sorts_and_deduplicates() {
+        let scopes = [
+            ApiScope::DevicesWake,
+            ApiScope::DevicesRead,
+            ApiScope::DevicesWake,
+        ];
+        let joined = ApiScope::join(&scopes);
+        assert_eq!(joined, "devices:read|devices:wake");
+        assert_eq!(
+            ApiScope::parse_scopes(&joined),
+            [ApiScope::DevicesRead, ApiScope::DevicesWake]
+        );
+    }
+}
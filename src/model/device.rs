@@ -0,0 +1,255 @@
+use crate::{
+    app_state::AppState,
+    auth::ctx::Ctx,
+    configuration::WolSettings,
+    model::device_event::{DeviceEvent, DeviceEventKind},
+    probe::ProbeMethod,
+    wol::{
+        error::WolError,
+        mac::MacAddress,
+        packet::MagicPacket,
+        secure_on::{SecureOn, SecureOnCipher},
+        Transport, WakeReport, WakeTarget,
+    },
+};
+use chrono::{DateTime, Utc};
+use sqlx::{prelude::FromRow, SqlitePool};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, serde::Serialize, FromRow)]
+pub struct Device {
+    pub id: Uuid,
+    pub mac_address: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub on: bool,
+    /// Encrypted SecureOn password, it's write only: responses only tell if it's set.
+    #[serde(rename = "has_secure_on", serialize_with = "serialize_is_some")]
+    pub secure_on: Option<Vec<u8>>,
+    /// Directed broadcast address, falls back to [`WolSettings::broadcast_address`].
+    pub broadcast_address: Option<String>,
+    /// Falls back to [`WolSettings::port`].
+    pub wol_port: Option<u16>,
+    /// Interface the magic packet must leave from, required by [`Transport::Ethernet`].
+    pub interface: Option<String>,
+    pub transport: Transport,
+    /// Address used by the prober, ARP can resolve it from the mac address when missing.
+    pub ip_address: Option<String>,
+    /// Falls back to [`crate::configuration::ProberSettings::method`].
+    pub probe_method: Option<ProbeMethod>,
+    /// Port for [`ProbeMethod::Tcp`], falls back to [`crate::configuration::ProberSettings::tcp_port`].
+    pub probe_port: Option<u16>,
+    /// Seconds between probes, falls back to [`crate::configuration::ProberSettings::interval_secs`].
+    pub probe_interval: Option<u32>,
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Average of the last observed boot times, see [`crate::probe::wait_until_online`].
+    pub expected_wake_ms: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NewDevice {
+    pub mac_address: MacAddress,
+    pub name: String,
+    pub description: Option<String>,
+    pub secure_on: Option<SecureOn>,
+    pub broadcast_address: Option<IpAddr>,
+    pub wol_port: Option<u16>,
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub transport: Transport,
+    pub ip_address: Option<IpAddr>,
+    pub probe_method: Option<ProbeMethod>,
+    pub probe_port: Option<u16>,
+    pub probe_interval: Option<u32>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DeviceUpdate {
+    pub mac_address: Option<MacAddress>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Missing leaves the SecureOn password untouched, `null` removes it.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub secure_on: Option<Option<SecureOn>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub broadcast_address: Option<Option<IpAddr>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub wol_port: Option<Option<u16>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub interface: Option<Option<String>>,
+    pub transport: Option<Transport>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub ip_address: Option<Option<IpAddr>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub probe_method: Option<Option<ProbeMethod>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub probe_port: Option<Option<u16>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub probe_interval: Option<Option<u32>>,
+}
+
+fn serialize_is_some<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_bool(value.is_some())
+}
+
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+impl Device {
+    pub fn mac(&self) -> Result<MacAddress, WolError> {
+        self.mac_address.parse()
+    }
+
+    pub fn magic_packet(&self, cipher: &SecureOnCipher) -> Result<MagicPacket, WolError> {
+        let mac_address = self.mac()?;
+        match &self.secure_on {
+            Some(secure_on) => Ok(MagicPacket::with_secure_on(
+                &mac_address,
+                &cipher.decrypt(self.id, secure_on)?,
+            )),
+            None => Ok(MagicPacket::new(&mac_address)),
+        }
+    }
+
+    pub fn wake_target(&self, defaults: &WolSettings) -> Result<WakeTarget, WolError> {
+        if self.transport == Transport::Ethernet {
+            return self
+                .interface
+                .clone()
+                .map(WakeTarget::ethernet)
+                .ok_or(WolError::MissingInterface);
+        }
+        let broadcast_address = match &self.broadcast_address {
+            Some(address) => address
+                .parse()
+                .map_err(|_| WolError::InvalidBroadcastAddress(address.clone()))?,
+            None => defaults.broadcast_address,
+        };
+        let port = self.wol_port.unwrap_or(defaults.port);
+        Ok(WakeTarget::udp(
+            SocketAddr::new(broadcast_address, port),
+            self.interface.clone(),
+        ))
+    }
+
+    /// Send the magic packet and let the event listeners know.
+    pub async fn power_on(&self, state: &AppState) -> Result<WakeReport, WolError> {
+        let packet = self.magic_packet(&state.secure_on_cipher)?;
+        let target = self.wake_target(&state.wol)?;
+        let report = crate::wol::send_magic_packet(&packet, &target).await?;
+        state.publish(DeviceEvent::new(self.id, DeviceEventKind::PowerOnSent));
+        Ok(report)
+    }
+
+    /// Devices with `device_id`, or every device when `None`,
+    /// restricted to the ones visible to `user_id` unless it's `None`.
+    async fn select(
+        pool: &SqlitePool,
+        device_id: Option<Uuid>,
+        user_id: Option<Uuid>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            Device,
+            r#"SELECT d.id as "id: Uuid", d.mac_address, d.name, d.description, d.`on` as "on: bool", d.secure_on,
+                d.broadcast_address, d.wol_port as "wol_port: u16", d.interface,
+                d.transport as "transport: Transport", d.ip_address,
+                d.probe_method as "probe_method: ProbeMethod", d.probe_port as "probe_port: u16",
+                d.probe_interval as "probe_interval: u32", d.last_seen as "last_seen: DateTime<Utc>",
+                (SELECT CAST(AVG(b.duration_ms) AS INTEGER) FROM device_boot_times b
+                    WHERE b.rowid IN (
+                        SELECT rowid FROM device_boot_times
+                        WHERE device_id = d.id
+                        ORDER BY recorded_at DESC LIMIT 10
+                    )) as "expected_wake_ms: i64"
+            FROM devices d
+            WHERE ($1 IS NULL OR d.id = $1)
+                AND ($2 IS NULL OR EXISTS (
+                    SELECT 1 FROM user_devices ud
+                    WHERE ud.device_id = d.id AND ud.user_id = $2 AND ud.visible = 1
+                ))
+            ORDER BY d.name"#,
+            device_id,
+            user_id,
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Fetch a device if `ctx` can see it: admins see every device,
+    /// users only the ones assigned to them and still `visible`.
+    pub async fn fetch_visible(
+        pool: &SqlitePool,
+        ctx: &Ctx,
+        device_id: Uuid,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let user_id = (!ctx.is_admin()).then_some(ctx.user_id);
+        Ok(Self::select(pool, Some(device_id), user_id).await?.pop())
+    }
+
+    /// Ids of the devices assigned to `user_id`, what a non admin can see.
+    pub async fn visible_ids(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT device_id as "device_id: Uuid" FROM user_devices
+            WHERE user_id = $1 AND visible = 1"#,
+            user_id,
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Devices assigned to a user in `user_devices`, even for admins.
+    pub async fn is_assigned(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        device_id: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM user_devices
+                WHERE device_id = $1 AND user_id = $2 AND visible = 1
+            ) as "assigned: bool""#,
+            device_id,
+            user_id,
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// The owner added the device, users it's shared with only have an assignment.
+    pub async fn is_owned(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        device_id: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM user_devices
+                WHERE device_id = $1 AND user_id = $2 AND owner = 1
+            ) as "owned: bool""#,
+            device_id,
+            user_id,
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// List every device `ctx` can see, see [`Device::fetch_visible`].
+    pub async fn list_visible(pool: &SqlitePool, ctx: &Ctx) -> Result<Vec<Self>, sqlx::Error> {
+        let user_id = (!ctx.is_admin()).then_some(ctx.user_id);
+        Self::select(pool, None, user_id).await
+    }
+
+    /// Every device, regardless of who can see it. Only for background tasks.
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        Self::select(pool, None, None).await
+    }
+}
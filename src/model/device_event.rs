@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceEventKind {
+    PowerOnSent,
+    CameOnline,
+    WentOffline,
+}
+
+impl DeviceEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceEventKind::PowerOnSent => "power_on_sent",
+            DeviceEventKind::CameOnline => "came_online",
+            DeviceEventKind::WentOffline => "went_offline",
+        }
+    }
+}
+
+/// Device state change published on `AppState::device_events`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceEvent {
+    pub device_id: Uuid,
+    pub kind: DeviceEventKind,
+    pub at: DateTime<Utc>,
+}
+
+impl DeviceEvent {
+    pub fn new(device_id: Uuid, kind: DeviceEventKind) -> Self {
+        Self {
+            device_id,
+            kind,
+            at: Utc::now(),
+        }
+    }
+}
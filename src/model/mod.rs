@@ -1,5 +1,10 @@
+pub mod api_token;
 pub mod device;
+pub mod device_event;
 pub mod device_type;
 pub mod role;
+pub mod schedule;
+pub mod session;
 pub mod user;
 pub mod user_request;
+pub mod webauthn_credential;
@@ -0,0 +1,346 @@
+use crate::auth::ctx::Ctx;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{prelude::FromRow, SqlitePool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Recurring wake-up of a device, owned by the user who created it.
+#[derive(Debug, Clone, serde::Serialize, FromRow)]
+pub struct Schedule {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub device_id: Uuid,
+    /// See [`parse_cron`] for the accepted syntax.
+    pub cron: String,
+    /// IANA time zone name, e.g. `Europe/Rome`.
+    pub timezone: String,
+    pub enabled: bool,
+    /// `None` when disabled or when the expression has no future occurrence.
+    pub next_run: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NewSchedule {
+    pub device_id: Uuid,
+    pub cron: String,
+    pub timezone: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ScheduleUpdate {
+    pub cron: Option<String>,
+    pub timezone: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, FromRow)]
+pub struct ScheduleRun {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub ran_at: DateTime<Utc>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Runs kept per schedule, the oldest ones are dropped as new ones are recorded.
+const KEPT_RUNS: u32 = 100;
+
+/// Parse a cron expression.
+///
+/// The classic 5 fields form (`30 7 * * Mon-Fri`) runs at second 0,
+/// the 6 and 7 fields forms add seconds in front and the year at the end.
+/// The seconds must be a single value: the scheduler only wakes up every few seconds.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, cron::error::Error> {
+    let expression = expression.trim();
+    let mut fields = expression.split_whitespace();
+    if fields.clone().count() == 5 {
+        return cron::Schedule::from_str(&format!("0 {}", expression));
+    }
+    let second = fields.next().unwrap_or_default();
+    if !second.parse::<u8>().is_ok_and(|second| second < 60) {
+        return Err(cron::error::ErrorKind::Expression(format!(
+            "the seconds must be a single value, not {}",
+            second
+        ))
+        .into());
+    }
+    cron::Schedule::from_str(expression)
+}
+
+/// First occurrence of `schedule` in `timezone` strictly after `after`.
+///
+/// A time skipped by a daylight saving change doesn't fire that day, a repeated one fires once.
+pub fn next_occurrence(
+    schedule: &cron::Schedule,
+    timezone: Tz,
+    after: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    schedule
+        .after(&after.with_timezone(&timezone))
+        .next()
+        .map(|next| next.with_timezone(&Utc))
+}
+
+impl Schedule {
+    /// Next time this schedule fires after `after`, `None` when disabled or invalid.
+    pub fn next_run_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if !self.enabled {
+            return None;
+        }
+        let schedule = parse_cron(&self.cron).ok()?;
+        let timezone = Tz::from_str(&self.timezone).ok()?;
+        next_occurrence(&schedule, timezone, after)
+    }
+
+    /// Fetch a schedule if `ctx` owns it, admins can fetch any schedule.
+    pub async fn fetch_for(
+        pool: &SqlitePool,
+        ctx: &Ctx,
+        schedule_id: Uuid,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let is_admin = ctx.is_admin();
+        sqlx::query_as!(
+            Schedule,
+            r#"SELECT id as "id: Uuid", user_id as "user_id: Uuid", device_id as "device_id: Uuid",
+                cron, timezone, enabled as "enabled: bool",
+                next_run as "next_run: DateTime<Utc>", created_at as "created_at: DateTime<Utc>"
+            FROM schedules
+            WHERE id = $1 AND ($2 OR user_id = $3)"#,
+            schedule_id,
+            is_admin,
+            ctx.user_id,
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// List the schedules of `ctx`, every schedule for admins.
+    pub async fn list_for(pool: &SqlitePool, ctx: &Ctx) -> Result<Vec<Self>, sqlx::Error> {
+        let is_admin = ctx.is_admin();
+        sqlx::query_as!(
+            Schedule,
+            r#"SELECT id as "id: Uuid", user_id as "user_id: Uuid", device_id as "device_id: Uuid",
+                cron, timezone, enabled as "enabled: bool",
+                next_run as "next_run: DateTime<Utc>", created_at as "created_at: DateTime<Utc>"
+            FROM schedules
+            WHERE $1 OR user_id = $2
+            ORDER BY id"#,
+            is_admin,
+            ctx.user_id,
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Enabled schedules due at `now` whose owner is still active. Only for the scheduler.
+    pub async fn list_due(pool: &SqlitePool, now: DateTime<Utc>) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            Schedule,
+            r#"SELECT s.id as "id: Uuid", s.user_id as "user_id: Uuid", s.device_id as "device_id: Uuid",
+                s.cron, s.timezone, s.enabled as "enabled: bool",
+                s.next_run as "next_run: DateTime<Utc>", s.created_at as "created_at: DateTime<Utc>"
+            FROM schedules s
+            JOIN users u ON u.id = s.user_id
+            WHERE s.enabled = 1 AND s.next_run IS NOT NULL AND s.next_run <= $1 AND u.active = 1
+            ORDER BY s.next_run"#,
+            now,
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn set_next_run(
+        pool: &SqlitePool,
+        schedule_id: Uuid,
+        next_run: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE schedules SET next_run=$1 WHERE id=$2",
+            next_run,
+            schedule_id,
+        )
+        .execute(pool)
+        .await
+        .map(|_| ())
+    }
+
+    /// Store the outcome of a run, only the last [`KEPT_RUNS`] runs of the schedule are kept.
+    pub async fn record_run(
+        pool: &SqlitePool,
+        schedule_id: Uuid,
+        error: Option<String>,
+    ) -> Result<ScheduleRun, sqlx::Error> {
+        let run = ScheduleRun {
+            id: Uuid::now_v7(),
+            schedule_id,
+            ran_at: Utc::now(),
+            success: error.is_none(),
+            error,
+        };
+        sqlx::query!(
+            r#"INSERT INTO schedule_runs(id, schedule_id, ran_at, success, error)
+                VALUES ($1, $2, $3, $4, $5)"#,
+            run.id,
+            run.schedule_id,
+            run.ran_at,
+            run.success,
+            run.error,
+        )
+        .execute(pool)
+        .await?;
+        sqlx::query!(
+            r#"DELETE FROM schedule_runs
+            WHERE schedule_id = $1 AND id NOT IN (
+                SELECT id FROM schedule_runs
+                WHERE schedule_id = $1
+                ORDER BY ran_at DESC, id DESC
+                LIMIT $2
+            )"#,
+            run.schedule_id,
+            KEPT_RUNS,
+        )
+        .execute(pool)
+        .await?;
+        Ok(run)
+    }
+
+    /// Most recent runs first.
+    pub async fn list_runs(
+        pool: &SqlitePool,
+        schedule_id: Uuid,
+        limit: u32,
+    ) -> Result<Vec<ScheduleRun>, sqlx::Error> {
+        sqlx::query_as!(
+            ScheduleRun,
+            r#"SELECT id as "id: Uuid", schedule_id as "schedule_id: Uuid",
+                ran_at as "ran_at: DateTime<Utc>", success as "success: bool", error
+            FROM schedule_runs
+            WHERE schedule_id = $1
+            ORDER BY ran_at DESC, id DESC
+            LIMIT $2"#,
+            schedule_id,
+            limit,
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().to_utc()
+    }
+
+    fn next(cron: &str, timezone: Tz, after: &str) -> DateTime<Utc> {
+        next_occurrence(&parse_cron(cron).unwrap(), timezone, utc(after)).unwrap()
+    }
+
+    #[test]
+    fn five_fields_run_at_second_zero() {
+        assert_eq!(
+            next("30 7 * * Mon-Fri", Tz::UTC, "2025-03-14T08:00:00Z"),
+            utc("2025-03-17T07:30:00Z")
+        );
+        assert_eq!(
+            next("0 30 7 * * Mon-Fri 2025", Tz::UTC, "2025-03-14T08:00:00Z"),
+            utc("2025-03-17T07:30:00Z")
+        );
+    }
+
+    #[test]
+    fn second_level_expressions_are_refused() {
+        for cron in [
+            "*/5 * * * * *",
+            "0-30 30 7 * * *",
+            "60 30 7 * * *",
+            "* * * *",
+        ] {
+            assert!(parse_cron(cron).is_err(), "{cron}");
+        }
+    }
+
+    #[test]
+    fn occurrences_follow_the_time_zone_offset() {
+        let rome = Tz::Europe__Rome;
+        // friday in winter time, monday in summer time
+        assert_eq!(
+            next("30 7 * * Mon-Fri", rome, "2025-03-28T07:00:00Z"),
+            rome.with_ymd_and_hms(2025, 3, 31, 7, 30, 0)
+                .unwrap()
+                .to_utc()
+        );
+        assert_eq!(
+            next("30 7 * * Mon-Fri", rome, "2025-03-28T07:00:00Z"),
+            utc("2025-03-31T05:30:00Z")
+        );
+    }
+
+    #[test]
+    fn daylight_saving_transitions() {
+        let rome = Tz::Europe__Rome;
+        // 02:30 doesn't exist on the 30th of march
+        println!("{}", next("30 2 * * *", rome, "2025-03-29T12:00:00Z"));
+        // and happens twice on the 26th of october
+        let first = next("30 2 * * *", rome, "2025-10-25T12:00:00Z");
+        println!(
+            "{} {}",
+            first,
+            next("30 2 * * *", rome, &first.to_rfc3339())
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_last_runs_are_kept() {
+        let state = crate::app_state::AppState::for_tests().await;
+        let pool = &state.db_pool;
+        let (user_id, device_id, schedule_id) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+        sqlx::query!(
+            r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret, active)
+            VALUES ($1, 'user', 'alice', '', 'alice@example.com', 'Alice', x'00', 1)"#,
+            user_id,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO devices(id, mac_address, name) VALUES ($1, 'aa:bb:cc:dd:ee:ff', 'nas')",
+            device_id,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        let created_at = Utc::now();
+        sqlx::query!(
+            r#"INSERT INTO schedules(id, user_id, device_id, cron, created_at)
+            VALUES ($1, $2, $3, '30 7 * * *', $4)"#,
+            schedule_id,
+            user_id,
+            device_id,
+            created_at,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let mut last = None;
+        for run in 0..KEPT_RUNS + 5 {
+            last = Some(
+                Schedule::record_run(pool, schedule_id, Some(run.to_string()))
+                    .await
+                    .unwrap(),
+            );
+        }
+        let runs = Schedule::list_runs(pool, schedule_id, KEPT_RUNS * 2)
+            .await
+            .unwrap();
+        assert_eq!(runs.len(), KEPT_RUNS as usize);
+        assert_eq!(runs[0].id, last.unwrap().id);
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::{DateTime, Utc};
+use sqlx::{prelude::FromRow, SqlitePool};
+use uuid::Uuid;
+
+/// A login of a user, alive as long as one of its refresh tokens is.
+#[derive(Debug, Clone, serde::Serialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Last time a refresh token of the session was issued.
+    pub last_used_at: DateTime<Utc>,
+    /// The session of the token making the request.
+    pub current: bool,
+}
+
+impl Session {
+    /// Sessions of `user_id` with a refresh token still usable, most recently used first.
+    pub async fn list_active(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        current: Option<Uuid>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query_as!(
+            Session,
+            r#"SELECT s.id as "id: Uuid", s.user_id as "user_id: Uuid", s.user_agent, s.ip_address,
+                s.created_at as "created_at: DateTime<Utc>", s.last_used_at as "last_used_at: DateTime<Utc>",
+                s.id IS $3 as "current!: bool"
+            FROM sessions s
+            WHERE s.user_id = $1 AND EXISTS (
+                SELECT 1 FROM refresh_tokens t
+                WHERE t.family_id = s.id AND t.rotated_at IS NULL AND t.revoked_at IS NULL
+                    AND t.expires_at > $2
+            )
+            ORDER BY s.last_used_at DESC"#,
+            user_id,
+            now,
+            current,
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Tell if `session_id` belongs to `user_id` and is still active.
+    pub async fn is_active(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM refresh_tokens
+                WHERE family_id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > $3
+            ) as "active: bool""#,
+            session_id,
+            user_id,
+            now,
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Revoke every session of `user_id`, but `except` when given.
+    pub async fn revoke_all(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        except: Option<Uuid>,
+    ) -> Result<u64, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query!(
+            r#"UPDATE refresh_tokens SET revoked_at=$1
+            WHERE user_id=$2 AND revoked_at IS NULL AND ($3 IS NULL OR family_id IS NOT $3)"#,
+            now,
+            user_id,
+            except,
+        )
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app_state::{AppState, SharedAppState},
+        auth::{
+            ctx::Ctx,
+            refresh_token::{self, ClientInfo},
+        },
+        model::role::Role,
+    };
+
+    async fn user(state: &AppState, username: &str) -> Uuid {
+        let user_id = Uuid::now_v7();
+        let email = format!("{username}@example.com");
+        sqlx::query!(
+            r#"INSERT INTO users(id, roles, username, password, email, full_name, totp_secret, active)
+            VALUES ($1, 'user', $2, '', $3, $2, x'00', 1)"#,
+            user_id,
+            username,
+            email,
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        user_id
+    }
+
+    async fn login(state: &SharedAppState, user_id: Uuid) -> Uuid {
+        let ctx = Ctx::new(user_id, vec![Role::User]);
+        let (ctx, _) = refresh_token::issue(state, &ctx, &ClientInfo::default())
+            .await
+            .unwrap();
+        ctx.sid.unwrap()
+    }
+
+    #[tokio::test]
+    async fn only_active_sessions_of_the_user_are_listed() {
+        let state = AppState::for_tests().await;
+        let (alice, bob) = (user(&state, "alice").await, user(&state, "bob").await);
+        let (first, second, revoked) = (
+            login(&state, alice).await,
+            login(&state, alice).await,
+            login(&state, alice).await,
+        );
+        let others = login(&state, bob).await;
+        refresh_token::revoke_session(&state.db_pool, revoked)
+            .await
+            .unwrap();
+
+        let sessions = Session::list_active(&state.db_pool, alice, Some(first))
+            .await
+            .unwrap();
+        let mut ids = sessions.iter().map(|s| s.id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![first, second]);
+        assert!(sessions.iter().all(|s| s.current == (s.id == first)));
+
+        assert!(Session::is_active(&state.db_pool, alice, first)
+            .await
+            .unwrap());
+        assert!(!Session::is_active(&state.db_pool, alice, revoked)
+            .await
+            .unwrap());
+        // a session id of another user
+        assert!(!Session::is_active(&state.db_pool, alice, others)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn revoking_all_sessions_can_keep_one() {
+        let state = AppState::for_tests().await;
+        let (alice, bob) = (user(&state, "alice").await, user(&state, "bob").await);
+        let (current, other) = (login(&state, alice).await, login(&state, alice).await);
+        let bobs = login(&state, bob).await;
+
+        Session::revoke_all(&state.db_pool, alice, Some(current))
+            .await
+            .unwrap();
+        assert!(Session::is_active(&state.db_pool, alice, current)
+            .await
+            .unwrap());
+        assert!(!Session::is_active(&state.db_pool, alice, other)
+            .await
+            .unwrap());
+
+        Session::revoke_all(&state.db_pool, alice, None)
+            .await
+            .unwrap();
+        assert!(!Session::is_active(&state.db_pool, alice, current)
+            .await
+            .unwrap());
+        assert!(Session::is_active(&state.db_pool, bob, bobs).await.unwrap());
+    }
+}
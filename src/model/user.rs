@@ -1,6 +1,6 @@
 use super::role::Role;
 use chrono::NaiveDate;
-use sqlx::prelude::FromRow;
+use sqlx::{prelude::FromRow, SqliteConnection};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize, FromRow)]
@@ -30,4 +30,24 @@ impl User {
         roles.dedup();
         self.roles = roles.join("|");
     }
+
+    /// `base` or the first free `base2`, `base3`... for an account created from another source.
+    pub async fn available_username(
+        conn: &mut SqliteConnection,
+        base: &str,
+    ) -> Result<String, sqlx::Error> {
+        let mut username = base.to_string();
+        let mut suffix = 1;
+        while sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM users WHERE username = $1) as "taken!: bool""#,
+            username
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        {
+            suffix += 1;
+            username = format!("{}{}", base, suffix);
+        }
+        Ok(username)
+    }
 }
@@ -1,3 +1,5 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::{prelude::FromRow, SqlitePool};
 use uuid::Uuid;
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -5,3 +7,78 @@ pub struct UserSignupRequest {
     pub user_id: Uuid,
     pub request_text: String,
 }
+
+/// Signup request with the infos of the user who sent it.
+#[derive(Debug, serde::Serialize, FromRow)]
+pub struct UserRequest {
+    pub user_id: Uuid,
+    pub request_text: String,
+    pub username: String,
+    pub email: String,
+    pub request_date: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct UserRequestFilter {
+    /// Case insensitive match on username or email.
+    pub search: Option<String>,
+    pub requested_from: Option<NaiveDate>,
+    pub requested_to: Option<NaiveDate>,
+}
+
+/// `search` as a literal in a `LIKE ... ESCAPE '\'` pattern.
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl UserRequest {
+    pub async fn fetch(pool: &SqlitePool, user_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            UserRequest,
+            r#"SELECT r.user_id as "user_id: Uuid", r.request_text, u.username, u.email,
+                u.request_date as "request_date: NaiveDateTime"
+            FROM users_signup_requests r
+            JOIN users u ON u.id = r.user_id
+            WHERE r.user_id = $1"#,
+            user_id,
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Page of requests after `cursor`, ids are UUIDv7 so this is the request order too.
+    pub async fn list_page(
+        pool: &SqlitePool,
+        filter: &UserRequestFilter,
+        cursor: Option<Uuid>,
+        limit: u32,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let search = filter
+            .search
+            .as_ref()
+            .map(|search| format!("%{}%", escape_like(search.trim())));
+        sqlx::query_as!(
+            UserRequest,
+            r#"SELECT r.user_id as "user_id: Uuid", r.request_text, u.username, u.email,
+                u.request_date as "request_date: NaiveDateTime"
+            FROM users_signup_requests r
+            JOIN users u ON u.id = r.user_id
+            WHERE ($1 IS NULL OR r.user_id > $1)
+                AND ($2 IS NULL OR u.username LIKE $2 ESCAPE '\' OR u.email LIKE $2 ESCAPE '\')
+                AND ($3 IS NULL OR date(u.request_date) >= $3)
+                AND ($4 IS NULL OR date(u.request_date) <= $4)
+            ORDER BY r.user_id
+            LIMIT $5"#,
+            cursor,
+            search,
+            filter.requested_from,
+            filter.requested_to,
+            limit,
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
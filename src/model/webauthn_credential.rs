@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+use sqlx::{prelude::FromRow, SqlitePool};
+use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
+
+/// A passkey registered by a user as second factor, a user can have several.
+#[derive(Debug, Clone, serde::Serialize, FromRow)]
+pub struct WebauthnCredential {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl WebauthnCredential {
+    pub async fn list_for(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            WebauthnCredential,
+            r#"SELECT id as "id: Uuid", user_id as "user_id: Uuid", name,
+                created_at as "created_at: DateTime<Utc>", last_used_at as "last_used_at: DateTime<Utc>"
+            FROM webauthn_credentials
+            WHERE user_id = $1
+            ORDER BY id"#,
+            user_id,
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Passkeys of `user_id` with the id of their row.
+    pub async fn passkeys_for(
+        pool: &SqlitePool,
+        user_id: Uuid,
+    ) -> Result<Vec<(Uuid, Passkey)>, anyhow::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT id as "id: Uuid", passkey FROM webauthn_credentials WHERE user_id = $1"#,
+            user_id,
+        )
+        .fetch_all(pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| Ok((row.id, serde_json::from_str(&row.passkey)?)))
+            .collect()
+    }
+
+    pub async fn insert(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        name: String,
+        passkey: &Passkey,
+    ) -> Result<Self, anyhow::Error> {
+        let credential = WebauthnCredential {
+            id: Uuid::now_v7(),
+            user_id,
+            name,
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+        let credential_id = passkey.cred_id().to_vec();
+        let passkey = serde_json::to_string(passkey)?;
+        sqlx::query!(
+            r#"INSERT INTO webauthn_credentials(id, user_id, credential_id, name, passkey, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6)"#,
+            credential.id,
+            credential.user_id,
+            credential_id,
+            credential.name,
+            passkey,
+            credential.created_at,
+        )
+        .execute(pool)
+        .await?;
+        Ok(credential)
+    }
+
+    /// Record a use of the passkey, with its updated signature counter.
+    pub async fn record_use(
+        pool: &SqlitePool,
+        id: Uuid,
+        passkey: &Passkey,
+    ) -> Result<(), anyhow::Error> {
+        let passkey = serde_json::to_string(passkey)?;
+        let now = Utc::now();
+        sqlx::query!(
+            "UPDATE webauthn_credentials SET passkey=$1, last_used_at=$2 WHERE id=$3",
+            passkey,
+            now,
+            id,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete a passkey of `user_id`, `false` when there's no such passkey.
+    pub async fn delete(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        credential_id: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM webauthn_credentials WHERE id=$1 AND user_id=$2",
+            credential_id,
+            user_id,
+        )
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::wol::mac::MacAddress;
+use std::net::{IpAddr, Ipv4Addr};
+
+const ARP_TABLE: &str = "/proc/net/arp";
+// ATF_COM: the entry is resolved
+const ARP_FLAG_COMPLETE: u32 = 0x2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpEntry {
+    pub ip_address: Ipv4Addr,
+    pub mac_address: MacAddress,
+}
+
+fn parse_arp_table(table: &str) -> Vec<ArpEntry> {
+    // IP address  HW type  Flags  HW address  Mask  Device
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let ip_address = columns.next()?.parse().ok()?;
+            let flags = columns.nth(1)?.trim_start_matches("0x");
+            let flags = u32::from_str_radix(flags, 16).ok()?;
+            let mac_address = columns.next()?.parse().ok()?;
+            (flags & ARP_FLAG_COMPLETE != 0).then_some(ArpEntry {
+                ip_address,
+                mac_address,
+            })
+        })
+        .collect()
+}
+
+/// Resolved entries of the kernel neighbour table, empty where it's not available.
+pub async fn arp_table() -> Vec<ArpEntry> {
+    match tokio::fs::read_to_string(ARP_TABLE).await {
+        Ok(table) => parse_arp_table(&table),
+        Err(e) => {
+            tracing::debug!("can't read {}: {}", ARP_TABLE, e);
+            Vec::new()
+        }
+    }
+}
+
+pub async fn find_by_mac(mac_address: &MacAddress) -> Option<ArpEntry> {
+    arp_table()
+        .await
+        .into_iter()
+        .find(|entry| entry.mac_address == *mac_address)
+}
+
+/// Look for `mac_address` in the neighbour table.
+///
+/// When the device `ip_address` is known a datagram is sent to it first,
+/// so the kernel has to resolve (or fail to resolve) it again.
+pub async fn is_present(
+    mac_address: &MacAddress,
+    ip_address: Option<IpAddr>,
+    timeout: std::time::Duration,
+) -> std::io::Result<bool> {
+    if let Some(IpAddr::V4(ip_address)) = ip_address {
+        let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        // discard port, we only care about the neighbour resolution
+        let _ = socket.send_to(&[0], (ip_address, 9)).await;
+        let deadline = tokio::time::Instant::now() + timeout;
+        while tokio::time::Instant::now() < deadline {
+            let present = arp_table()
+                .await
+                .iter()
+                .any(|entry| entry.mac_address == *mac_address && entry.ip_address == ip_address);
+            if present {
+                return Ok(true);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        return Ok(false);
+    }
+    Ok(find_by_mac(mac_address).await.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_resolved_entries_are_listed() {
+        let table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.10     0x1         0x2         aa:bb:cc:dd:ee:ff     *        eth0
+192.168.1.11     0x1         0x0         00:00:00:00:00:00     *        eth0
+192.168.1.12     0x1         0x6         11:22:33:44:55:66     *        eth0
+not-an-ip        0x1         0x2         aa:bb:cc:dd:ee:ff     *        eth0
+192.168.1.13     0x1
+";
+        assert_eq!(
+            parse_arp_table(table),
+            vec![
+                ArpEntry {
+                    ip_address: Ipv4Addr::new(192, 168, 1, 10),
+                    mac_address: "aa:bb:cc:dd:ee:ff".parse().unwrap(),
+                },
+                ArpEntry {
+                    ip_address: Ipv4Addr::new(192, 168, 1, 12),
+                    mac_address: "11:22:33:44:55:66".parse().unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_table_without_entries_is_empty() {
+        assert!(parse_arp_table("").is_empty());
+        assert!(parse_arp_table(
+            "IP address       HW type     Flags       HW address            Mask     Device\n"
+        )
+        .is_empty());
+    }
+}
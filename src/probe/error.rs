@@ -0,0 +1,32 @@
+use axum::{http::StatusCode, response::IntoResponse};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProbeError {
+    #[error("Device has no ip address and it's not in the arp table")]
+    MissingAddress,
+    #[error("Invalid ip address: {0}")]
+    InvalidAddress(String),
+    #[error(transparent)]
+    WolError(#[from] crate::wol::error::WolError),
+    #[error("Can't probe device: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for ProbeError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            ProbeError::MissingAddress | ProbeError::InvalidAddress(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            ProbeError::WolError(wol_error) => wol_error.into_response(),
+            ProbeError::IoError(_) => (StatusCode::BAD_GATEWAY, self.to_string()).into_response(),
+            ProbeError::UnexpectedError(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("something went wrong {}", error),
+            )
+                .into_response(),
+        }
+    }
+}
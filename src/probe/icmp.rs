@@ -0,0 +1,149 @@
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{
+    io::{self, Read as _},
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMP_HEADER_LEN: usize = 8;
+const PAYLOAD: &[u8] = b"wol_server";
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data.chunks(2).fold(0u32, |sum, chunk| {
+        let word = match chunk {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            [high] => u16::from_be_bytes([*high, 0]),
+            _ => 0,
+        };
+        sum + word as u32
+    });
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(address: &IpAddr, identifier: u16, sequence: u16) -> Vec<u8> {
+    let message_type = match address {
+        IpAddr::V4(_) => ICMP_ECHO_REQUEST,
+        IpAddr::V6(_) => ICMPV6_ECHO_REQUEST,
+    };
+    let mut packet = vec![message_type, 0, 0, 0];
+    packet.extend_from_slice(&identifier.to_be_bytes());
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    // the kernel computes the ICMPv6 checksum, it needs the pseudo header
+    if address.is_ipv4() {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// Prefer unprivileged ICMP sockets (`net.ipv4.ping_group_range`),
+/// fall back to raw sockets when the process has `CAP_NET_RAW`.
+fn icmp_socket(address: &IpAddr) -> io::Result<(Socket, bool)> {
+    let (domain, protocol) = match address {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+        Ok(socket) => Ok((socket, false)),
+        Err(_) => Socket::new(domain, Type::RAW, Some(protocol)).map(|socket| (socket, true)),
+    }
+}
+
+/// Send one ICMP echo request and wait up to `timeout` for the matching reply.
+///
+/// Blocking, run it with [`crate::telemetry::spawn_blocking_with_tracing`].
+pub fn ping(address: IpAddr, timeout: Duration) -> io::Result<bool> {
+    let (socket, is_raw) = icmp_socket(&address)?;
+    let identifier = rand::random::<u16>();
+    let sequence = rand::random::<u16>();
+    socket.connect(&SockAddr::from(SocketAddr::new(address, 0)))?;
+    match socket.send(&echo_request(&address, identifier, sequence)) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::HostUnreachable => return Ok(false),
+        Err(e) => return Err(e),
+    }
+
+    let expected_reply = match address {
+        IpAddr::V4(_) => ICMP_ECHO_REPLY,
+        IpAddr::V6(_) => ICMPV6_ECHO_REPLY,
+    };
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 1500];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(false);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let received = match (&socket).read(&mut buffer) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(false)
+            }
+            Err(e) if e.kind() == io::ErrorKind::HostUnreachable => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let mut reply = &buffer[..received];
+        // raw IPv4 sockets hand us the ip header too
+        if is_raw && address.is_ipv4() && !reply.is_empty() {
+            let header_len = ((reply[0] & 0x0F) as usize) * 4;
+            reply = reply.get(header_len..).unwrap_or_default();
+        }
+        if reply.len() < ICMP_HEADER_LEN || reply[0] != expected_reply {
+            continue;
+        }
+        // unprivileged sockets rewrite the identifier, the kernel already filters on it
+        let identifier_matches = !is_raw || reply[4..6] == identifier.to_be_bytes();
+        if identifier_matches && reply[6..8] == sequence.to_be_bytes() {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn checksum_is_the_ones_complement_sum() {
+        // RFC 1071 example
+        assert_eq!(
+            checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+            !0xddf2
+        );
+        // an odd byte is padded with a zero
+        assert_eq!(checksum(&[0x01]), !0x0100);
+        assert_eq!(checksum(&[]), 0xffff);
+    }
+
+    #[test]
+    fn ipv4_echo_requests_carry_their_checksum() {
+        let packet = echo_request(&IpAddr::V4(Ipv4Addr::LOCALHOST), 0x1234, 0xabcd);
+        assert_eq!(packet[..2], [ICMP_ECHO_REQUEST, 0]);
+        assert_eq!(packet[4..8], [0x12, 0x34, 0xab, 0xcd]);
+        assert_eq!(&packet[ICMP_HEADER_LEN..], PAYLOAD);
+        // summing a packet with its checksum gives zero
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn ipv6_echo_requests_leave_the_checksum_to_the_kernel() {
+        let packet = echo_request(&IpAddr::V6(Ipv6Addr::LOCALHOST), 1, 2);
+        assert_eq!(packet[..4], [ICMPV6_ECHO_REQUEST, 0, 0, 0]);
+        assert_eq!(packet[4..8], [0, 1, 0, 2]);
+    }
+}
@@ -0,0 +1,292 @@
+pub mod arp;
+pub mod error;
+pub mod icmp;
+
+use crate::{
+    app_state::{AppState, SharedAppState},
+    configuration::ProberSettings,
+    model::{
+        device::Device,
+        device_event::{DeviceEvent, DeviceEventKind},
+    },
+    telemetry::spawn_blocking_with_tracing,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use error::ProbeError;
+use sqlx::SqlitePool;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::{task::JoinSet, time::Instant};
+use uuid::Uuid;
+
+/// How often the prober looks for devices that are due for a probe.
+const PROBER_TICK: Duration = Duration::from_secs(5);
+
+/// How the prober decides if a device is up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ProbeMethod {
+    /// ICMP echo request.
+    Icmp,
+    /// TCP connect, a refused connection still means the host is up.
+    Tcp,
+    /// Presence of the device mac address in the kernel neighbour table.
+    Arp,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProbeResult {
+    pub device_id: Uuid,
+    pub method: ProbeMethod,
+    pub on: bool,
+    pub probed_at: DateTime<Utc>,
+}
+
+/// What happened while waiting for a device to boot after a magic packet.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum WaitOutcome {
+    /// The device answered before the magic packet was sent.
+    AlreadyOn,
+    Online {
+        boot_time_ms: u64,
+    },
+    Timeout {
+        waited_ms: u64,
+    },
+}
+
+/// Configured ip address of the device, else the one the arp table knows for its mac.
+async fn resolve_address(device: &Device) -> Result<IpAddr, ProbeError> {
+    if let Some(ip_address) = &device.ip_address {
+        return ip_address
+            .parse()
+            .map_err(|_| ProbeError::InvalidAddress(ip_address.clone()));
+    }
+    arp::find_by_mac(&device.mac()?)
+        .await
+        .map(|entry| IpAddr::V4(entry.ip_address))
+        .ok_or(ProbeError::MissingAddress)
+}
+
+async fn tcp_connect(address: SocketAddr, timeout: Duration) -> std::io::Result<bool> {
+    match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(address)).await {
+        Ok(Ok(_)) => Ok(true),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => Ok(true),
+        Ok(Err(e))
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::HostUnreachable | std::io::ErrorKind::NetworkUnreachable
+            ) =>
+        {
+            Ok(false)
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Check once if `device` is up, per device settings override the `[prober]` ones.
+#[tracing::instrument(name = "probe_device", skip_all, fields(device_id = %device.id))]
+pub async fn probe_device(
+    device: &Device,
+    settings: &ProberSettings,
+) -> Result<ProbeResult, ProbeError> {
+    let method = device.probe_method.unwrap_or(settings.method);
+    let timeout = Duration::from_millis(settings.timeout_ms);
+    let on = match method {
+        ProbeMethod::Icmp => {
+            let address = resolve_address(device).await?;
+            spawn_blocking_with_tracing(move || icmp::ping(address, timeout))
+                .await
+                .map_err(std::io::Error::other)??
+        }
+        ProbeMethod::Tcp => {
+            let address = resolve_address(device).await?;
+            let port = device.probe_port.unwrap_or(settings.tcp_port);
+            tcp_connect(SocketAddr::new(address, port), timeout).await?
+        }
+        ProbeMethod::Arp => {
+            let ip_address = device
+                .ip_address
+                .as_ref()
+                .and_then(|address| address.parse().ok());
+            arp::is_present(&device.mac()?, ip_address, timeout).await?
+        }
+    };
+    Ok(ProbeResult {
+        device_id: device.id,
+        method,
+        on,
+        probed_at: Utc::now(),
+    })
+}
+
+/// Like [`probe_device`], a device that can't be probed counts as offline.
+pub async fn probe_or_offline(device: &Device, settings: &ProberSettings) -> ProbeResult {
+    match probe_device(device, settings).await {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::warn!(
+                "can't probe device {}, counting it offline: {}",
+                device.id,
+                e
+            );
+            ProbeResult {
+                device_id: device.id,
+                method: device.probe_method.unwrap_or(settings.method),
+                on: false,
+                probed_at: Utc::now(),
+            }
+        }
+    }
+}
+
+/// Store the outcome of a probe, `last_seen` only moves when the device answered.
+///
+/// Publishes a [`DeviceEvent`] when the device changed state.
+pub async fn record(state: &AppState, result: &ProbeResult) -> Result<(), sqlx::Error> {
+    let changed = sqlx::query!(
+        r#"UPDATE devices SET `on`=$1 WHERE id=$2 AND `on`!=$1"#,
+        result.on,
+        result.device_id,
+    )
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected()
+        > 0;
+    if result.on {
+        sqlx::query!(
+            r#"UPDATE devices SET last_seen=$1 WHERE id=$2"#,
+            result.probed_at,
+            result.device_id,
+        )
+        .execute(&state.db_pool)
+        .await?;
+    }
+    if changed {
+        let kind = match result.on {
+            true => DeviceEventKind::CameOnline,
+            false => DeviceEventKind::WentOffline,
+        };
+        state.publish(DeviceEvent::new(result.device_id, kind));
+    }
+    Ok(())
+}
+
+/// Probe `device` until it answers or `timeout` expires, starting the clock at `since`.
+///
+/// Every probe is recorded, the boot time too when the device comes up.
+/// A probe that fails means the device isn't up yet.
+#[tracing::instrument(name = "wait_until_online", skip_all, fields(device_id = %device.id))]
+pub async fn wait_until_online(
+    state: &AppState,
+    device: &Device,
+    since: Instant,
+    timeout: Duration,
+) -> Result<WaitOutcome, ProbeError> {
+    let deadline = since + timeout;
+    let mut poll = tokio::time::interval(Duration::from_millis(state.prober.wake_poll_ms.get()));
+    poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    while Instant::now() < deadline {
+        poll.tick().await;
+        let result = probe_or_offline(device, &state.prober).await;
+        record(state, &result)
+            .await
+            .context("can't record probe result")?;
+        if result.on {
+            let boot_time = since.elapsed();
+            record_boot_time(&state.db_pool, device.id, boot_time)
+                .await
+                .context("can't record boot time")?;
+            return Ok(WaitOutcome::Online {
+                boot_time_ms: boot_time.as_millis() as u64,
+            });
+        }
+    }
+    Ok(WaitOutcome::Timeout {
+        waited_ms: since.elapsed().as_millis() as u64,
+    })
+}
+
+/// Observed boot durations feed `Device::expected_wake_ms`.
+pub async fn record_boot_time(
+    pool: &SqlitePool,
+    device_id: Uuid,
+    boot_time: Duration,
+) -> Result<(), sqlx::Error> {
+    let duration_ms = boot_time.as_millis() as i64;
+    let recorded_at = Utc::now();
+    sqlx::query!(
+        r#"INSERT INTO device_boot_times(device_id, duration_ms, recorded_at)
+            VALUES ($1, $2, $3)"#,
+        device_id,
+        duration_ms,
+        recorded_at,
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+async fn probe_due_devices(state: &SharedAppState, next_probe: &mut HashMap<Uuid, Instant>) {
+    let devices = match Device::list_all(&state.db_pool).await {
+        Ok(devices) => devices,
+        Err(e) => {
+            tracing::error!("can't list devices to probe: {}", e);
+            return;
+        }
+    };
+    next_probe.retain(|device_id, _| devices.iter().any(|device| device.id == *device_id));
+
+    let now = Instant::now();
+    let mut probes = JoinSet::new();
+    for device in devices {
+        if next_probe.get(&device.id).is_some_and(|due| *due > now) {
+            continue;
+        }
+        let interval = device
+            .probe_interval
+            .map(|interval| Duration::from_secs(interval as u64))
+            .unwrap_or(Duration::from_secs(state.prober.interval_secs));
+        next_probe.insert(device.id, now + interval);
+
+        let state = state.clone();
+        probes.spawn(async move {
+            let result = probe_or_offline(&device, &state.prober).await;
+            record(&state, &result)
+                .await
+                .context("can't record probe result")?;
+            Ok::<_, anyhow::Error>(result)
+        });
+    }
+    while let Some(probe) = probes.join_next().await {
+        match probe {
+            Ok(Ok(result)) => tracing::debug!(
+                "device {} is {}",
+                result.device_id,
+                if result.on { "on" } else { "off" }
+            ),
+            Ok(Err(e)) => tracing::warn!("{:#}", e),
+            Err(e) => tracing::error!("probe task failed: {}", e),
+        }
+    }
+}
+
+/// Periodically probe every device and keep `devices.on` and `devices.last_seen` up to date.
+pub fn spawn_prober(state: SharedAppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut next_probe = HashMap::new();
+        let mut ticker = tokio::time::interval(PROBER_TICK);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            probe_due_devices(&state, &mut next_probe).await;
+        }
+    })
+}
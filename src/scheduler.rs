@@ -0,0 +1,88 @@
+use crate::{
+    app_state::SharedAppState,
+    auth::ctx::Ctx,
+    model::{device::Device, schedule::Schedule},
+};
+use anyhow::Context;
+use chrono::Utc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// How often the scheduler looks for schedules that are due.
+const SCHEDULER_TICK: Duration = Duration::from_secs(15);
+
+/// Send the magic packet of a due schedule, the device must still be assigned to its owner.
+#[tracing::instrument(name = "run_schedule", skip_all, fields(schedule_id = %schedule.id, device_id = %schedule.device_id))]
+async fn run_schedule(state: &SharedAppState, schedule: &Schedule) -> anyhow::Result<()> {
+    // a ctx without roles only sees the devices assigned to the schedule owner
+    let owner = Ctx::new(schedule.user_id, Vec::new());
+    let device = Device::fetch_visible(&state.db_pool, &owner, schedule.device_id)
+        .await
+        .context("can't fetch device")?
+        .context("device is no longer assigned to the schedule owner")?;
+    let report = device.power_on(state).await?;
+    tracing::info!(
+        "scheduled power on sent to {} ({} bytes)",
+        report.mac_address,
+        report.bytes_sent
+    );
+    Ok(())
+}
+
+async fn run_due_schedules(state: &SharedAppState) {
+    let now = Utc::now();
+    let schedules = match Schedule::list_due(&state.db_pool, now).await {
+        Ok(schedules) => schedules,
+        Err(e) => {
+            tracing::error!("can't list due schedules: {}", e);
+            return;
+        }
+    };
+
+    let mut runs = JoinSet::new();
+    for schedule in schedules {
+        // missed runs (e.g. the server was down) are not caught up, only the next one is planned
+        let next_run = schedule.next_run_after(now);
+        if let Err(e) = Schedule::set_next_run(&state.db_pool, schedule.id, next_run).await {
+            tracing::error!("can't plan next run of schedule {}: {}", schedule.id, e);
+            continue;
+        }
+
+        let state = state.clone();
+        runs.spawn(async move {
+            let error = run_schedule(&state, &schedule)
+                .await
+                .err()
+                .map(|e| format!("{:#}", e));
+            Schedule::record_run(&state.db_pool, schedule.id, error)
+                .await
+                .context("can't record schedule run")
+        });
+    }
+    while let Some(run) = runs.join_next().await {
+        match run {
+            Ok(Ok(run)) if run.success => {
+                tracing::debug!("schedule {} ran", run.schedule_id)
+            }
+            Ok(Ok(run)) => tracing::warn!(
+                "schedule {} failed: {}",
+                run.schedule_id,
+                run.error.unwrap_or_default()
+            ),
+            Ok(Err(e)) => tracing::error!("{:#}", e),
+            Err(e) => tracing::error!("schedule task failed: {}", e),
+        }
+    }
+}
+
+/// Periodically wake the devices whose schedules are due.
+pub fn spawn_scheduler(state: SharedAppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SCHEDULER_TICK);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            run_due_schedules(&state).await;
+        }
+    })
+}
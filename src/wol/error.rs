@@ -0,0 +1,50 @@
+use axum::{http::StatusCode, response::IntoResponse};
+
+#[derive(thiserror::Error, Debug)]
+pub enum WolError {
+    #[error("Invalid mac address: {0}")]
+    InvalidMacAddress(String),
+    #[error("Invalid SecureOn password, expected 4 or 6 hex bytes")]
+    InvalidSecureOn,
+    #[error("Can't encrypt or decrypt the SecureOn password")]
+    SecureOnCipher,
+    #[error("Invalid broadcast address: {0}")]
+    InvalidBroadcastAddress(String),
+    #[error("Invalid network interface name: {0}")]
+    InvalidInterface(String),
+    #[error("Ethernet transport needs an interface")]
+    MissingInterface,
+    #[error("The server process lacks {0} to send this magic packet")]
+    MissingCapability(&'static str),
+    #[error("Transport {0} is not supported on this platform")]
+    UnsupportedTransport(&'static str),
+    #[error("Can't send magic packet to {target}: {source}")]
+    SendError {
+        target: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Magic packet was truncated, sent {sent} of {expected} bytes")]
+    Truncated { sent: usize, expected: usize },
+}
+
+impl IntoResponse for WolError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            WolError::InvalidMacAddress(_)
+            | WolError::InvalidSecureOn
+            | WolError::InvalidBroadcastAddress(_)
+            | WolError::InvalidInterface(_)
+            | WolError::MissingInterface => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            WolError::SecureOnCipher => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            WolError::MissingCapability(_) | WolError::UnsupportedTransport(_) => {
+                (StatusCode::SERVICE_UNAVAILABLE, self.to_string()).into_response()
+            }
+            WolError::SendError { .. } | WolError::Truncated { .. } => {
+                (StatusCode::BAD_GATEWAY, self.to_string()).into_response()
+            }
+        }
+    }
+}
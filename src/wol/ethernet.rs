@@ -0,0 +1,120 @@
+use super::{error::WolError, mac::MacAddress};
+
+/// EtherType reserved for Wake-on-LAN frames.
+pub const ETHER_TYPE_WOL: u16 = 0x0842;
+
+/// Error of a failed send, a missing capability surfaces as such.
+#[cfg(target_os = "linux")]
+fn send_error(interface: &str, source: std::io::Error) -> WolError {
+    match source.raw_os_error() {
+        Some(libc::EPERM) | Some(libc::EACCES) => WolError::MissingCapability("CAP_NET_RAW"),
+        _ => WolError::SendError {
+            target: interface.to_string(),
+            source,
+        },
+    }
+}
+
+/// `sockaddr_ll` of `destination` on the interface `interface_index`.
+#[cfg(target_os = "linux")]
+fn link_layer_address(interface_index: u32, destination: &MacAddress) -> socket2::SockAddr {
+    // SAFETY: `sockaddr_storage` is plain old data, all zeroes is a valid value.
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    // SAFETY: `sockaddr_storage` is large and aligned enough for any address.
+    let address = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_ll) };
+    address.sll_family = libc::AF_PACKET as libc::c_ushort;
+    address.sll_protocol = ETHER_TYPE_WOL.to_be();
+    address.sll_ifindex = interface_index as libc::c_int;
+    address.sll_halen = destination.as_bytes().len() as libc::c_uchar;
+    address.sll_addr[..destination.as_bytes().len()].copy_from_slice(destination.as_bytes());
+    let length = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+    // SAFETY: `storage` holds an initialised `sockaddr_ll` of `length` bytes.
+    unsafe { socket2::SockAddr::new(storage, length) }
+}
+
+/// Send `payload` as a layer 2 frame with EtherType 0x0842 out of `interface`.
+///
+/// Uses an `AF_PACKET`/`SOCK_DGRAM` socket, the kernel fills in the ethernet header
+/// with the interface address as source. Needs `CAP_NET_RAW`.
+#[cfg(target_os = "linux")]
+pub fn send_frame(
+    payload: &[u8],
+    interface: &str,
+    destination: &MacAddress,
+) -> Result<usize, WolError> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::{ffi::CString, io};
+
+    let interface_name =
+        CString::new(interface).map_err(|_| WolError::InvalidInterface(interface.to_string()))?;
+    // SAFETY: `interface_name` is a valid nul terminated string.
+    let interface_index = unsafe { libc::if_nametoindex(interface_name.as_ptr()) };
+    if interface_index == 0 {
+        return Err(send_error(interface, io::Error::last_os_error()));
+    }
+    let socket = Socket::new(
+        Domain::PACKET,
+        Type::DGRAM,
+        Some(Protocol::from(ETHER_TYPE_WOL.to_be() as libc::c_int)),
+    )
+    .map_err(|e| send_error(interface, e))?;
+    socket
+        .send_to(payload, &link_layer_address(interface_index, destination))
+        .map_err(|e| send_error(interface, e))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn send_frame(
+    _payload: &[u8],
+    _interface: &str,
+    _destination: &MacAddress,
+) -> Result<usize, WolError> {
+    Err(WolError::UnsupportedTransport("ethernet"))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn addresses_the_frame_to_the_destination() {
+        let destination: MacAddress = "01:23:45:67:89:ab".parse().unwrap();
+        let address = link_layer_address(3, &destination);
+        assert_eq!(address.family(), libc::AF_PACKET as libc::sa_family_t);
+        assert_eq!(
+            address.len() as usize,
+            std::mem::size_of::<libc::sockaddr_ll>()
+        );
+        // SAFETY: the address was built from a `sockaddr_ll`.
+        let address = unsafe { &*(address.as_ptr() as *const libc::sockaddr_ll) };
+        assert_eq!(u16::from_be(address.sll_protocol), ETHER_TYPE_WOL);
+        assert_eq!(address.sll_ifindex, 3);
+        assert_eq!(address.sll_halen, 6);
+        assert_eq!(&address.sll_addr[..6], destination.as_bytes());
+        assert_eq!(&address.sll_addr[6..], &[0, 0]);
+    }
+
+    #[test]
+    fn permission_errors_are_a_missing_capability() {
+        for errno in [libc::EPERM, libc::EACCES] {
+            assert!(matches!(
+                send_error("eth0", io::Error::from_raw_os_error(errno)),
+                WolError::MissingCapability("CAP_NET_RAW")
+            ));
+        }
+        assert!(matches!(
+            send_error("eth0", io::Error::from_raw_os_error(libc::ENETDOWN)),
+            WolError::SendError { target, .. } if target == "eth0"
+        ));
+    }
+
+    #[test]
+    fn unknown_interface_fails() {
+        let destination: MacAddress = "01:23:45:67:89:ab".parse().unwrap();
+        assert!(matches!(
+            send_frame(&[0; 102], "nosuchif0", &destination),
+            Err(WolError::SendError { .. })
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+use super::error::WolError;
+use std::{fmt::Display, str::FromStr};
+
+pub const MAC_ADDRESS_LEN: usize = 6;
+
+/// 48 bit hardware address of a device.
+///
+/// Accepts `aa:bb:cc:dd:ee:ff`, `aa-bb-cc-dd-ee-ff` and `aabbccddeeff`,
+/// it's always displayed in the lowercase `:` separated form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddress([u8; MAC_ADDRESS_LEN]);
+
+impl MacAddress {
+    pub const BROADCAST: MacAddress = MacAddress([0xFF; MAC_ADDRESS_LEN]);
+
+    pub fn new(bytes: [u8; MAC_ADDRESS_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; MAC_ADDRESS_LEN] {
+        &self.0
+    }
+}
+
+/// Exactly two hex digits, shared with the SecureOn password parsing.
+pub(crate) fn parse_hex_octet(octet: &str) -> Option<u8> {
+    // from_str_radix also takes a sign, e.g. "+f"
+    if octet.len() != 2 || !octet.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(octet, 16).ok()
+}
+
+impl FromStr for MacAddress {
+    type Err = WolError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || WolError::InvalidMacAddress(value.to_string());
+        let value = value.trim();
+        let octets: Vec<&str> = if value.contains(':') {
+            value.split(':').collect()
+        } else if value.contains('-') {
+            value.split('-').collect()
+        } else if value.len() == MAC_ADDRESS_LEN * 2 && value.is_ascii() {
+            (0..value.len())
+                .step_by(2)
+                .map(|i| &value[i..i + 2])
+                .collect()
+        } else {
+            return Err(invalid());
+        };
+        if octets.len() != MAC_ADDRESS_LEN {
+            return Err(invalid());
+        }
+
+        let mut bytes = [0u8; MAC_ADDRESS_LEN];
+        for (byte, octet) in bytes.iter_mut().zip(octets) {
+            *byte = parse_hex_octet(octet).ok_or_else(invalid)?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl serde::Serialize for MacAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for MacAddress {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: std::borrow::Cow<str> = serde::Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTES: [u8; MAC_ADDRESS_LEN] = [0xaa, 0xbb, 0xcc, 0x01, 0x02, 0xff];
+
+    #[test]
+    fn parses_every_separator() {
+        for value in [
+            "aa:bb:cc:01:02:ff",
+            "aa-bb-cc-01-02-ff",
+            "aabbcc0102ff",
+            "AA:BB:CC:01:02:FF",
+            " aa:bb:cc:01:02:ff ",
+        ] {
+            let mac: MacAddress = value.parse().unwrap();
+            assert_eq!(mac.as_bytes(), &BYTES, "{value}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_length() {
+        for value in [
+            "",
+            "aa:bb:cc:01:02",
+            "aa:bb:cc:01:02:ff:00",
+            "aabbcc0102",
+            "aabbcc0102ff00",
+            "a:bb:cc:01:02:ff",
+            "aaa:bb:cc:01:02:f",
+        ] {
+            assert!(value.parse::<MacAddress>().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn rejects_signed_octets() {
+        for value in ["+f:bb:cc:01:02:ff", "aa-bb-cc-01-02-+f", "+fbbcc0102ff"] {
+            assert!(value.parse::<MacAddress>().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn rejects_mixed_separators() {
+        assert!("aa:bb-cc:01:02:ff".parse::<MacAddress>().is_err());
+    }
+
+    #[test]
+    fn displays_lowercase_colon_separated() {
+        assert_eq!(MacAddress::new(BYTES).to_string(), "aa:bb:cc:01:02:ff");
+    }
+}
@@ -0,0 +1,227 @@
+pub mod error;
+pub mod ethernet;
+pub mod mac;
+pub mod packet;
+pub mod secure_on;
+
+use chrono::{DateTime, Utc};
+use error::WolError;
+use mac::MacAddress;
+use packet::MagicPacket;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+use crate::telemetry::spawn_blocking_with_tracing;
+
+// IFNAMSIZ includes the trailing nul
+const MAX_INTERFACE_NAME_LEN: usize = 15;
+
+/// How the magic packet reaches the device.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Transport {
+    /// UDP datagram, usually to a broadcast address on port 7 or 9.
+    #[default]
+    Udp,
+    /// Raw layer 2 frame with EtherType 0x0842, for firmware that ignores UDP.
+    Ethernet,
+}
+
+/// Where a magic packet is sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WakeTarget {
+    /// `interface` optionally forces the packet out of a specific NIC.
+    Udp {
+        address: SocketAddr,
+        interface: Option<String>,
+    },
+    /// Frame broadcast on the `interface` segment.
+    Ethernet { interface: String },
+}
+
+impl WakeTarget {
+    pub fn udp(address: SocketAddr, interface: Option<String>) -> Self {
+        Self::Udp { address, interface }
+    }
+
+    pub fn ethernet(interface: String) -> Self {
+        Self::Ethernet { interface }
+    }
+
+    pub fn transport(&self) -> Transport {
+        match self {
+            WakeTarget::Udp { .. } => Transport::Udp,
+            WakeTarget::Ethernet { .. } => Transport::Ethernet,
+        }
+    }
+
+    pub fn interface(&self) -> Option<&str> {
+        match self {
+            WakeTarget::Udp { interface, .. } => interface.as_deref(),
+            WakeTarget::Ethernet { interface } => Some(interface),
+        }
+    }
+}
+
+pub fn validate_interface_name(interface: &str) -> Result<(), WolError> {
+    let is_valid = !interface.is_empty()
+        && interface.len() <= MAX_INTERFACE_NAME_LEN
+        && interface
+            .chars()
+            .all(|c| c.is_ascii_graphic() && c != '/' && c != ':');
+    match is_valid {
+        true => Ok(()),
+        false => Err(WolError::InvalidInterface(interface.to_string())),
+    }
+}
+
+/// Outcome of a magic packet that left the server.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WakeReport {
+    pub mac_address: MacAddress,
+    pub secure_on: bool,
+    pub transport: Transport,
+    /// Only set for [`Transport::Udp`].
+    pub target: Option<SocketAddr>,
+    pub interface: Option<String>,
+    pub bytes_sent: usize,
+    pub sent_at: DateTime<Utc>,
+}
+
+fn udp_socket(address: SocketAddr, interface: Option<&str>) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_broadcast(true)?;
+    if let Some(interface) = interface {
+        bind_device(&socket, interface)?;
+    }
+    let bind_address = match address.ip() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    socket.bind(&SocketAddr::new(bind_address, 0).into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Force the packet out of `interface` with `SO_BINDTODEVICE`,
+/// routing by destination address picks the wrong NIC on multi-homed hosts.
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &Socket, interface: &str) -> std::io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_socket: &Socket, _interface: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "binding to an interface is only supported on linux",
+    ))
+}
+
+/// Send `packet` to `target` with the transport the target asks for.
+///
+/// Any reachable address works as UDP target too (e.g. a listener on loopback).
+#[tracing::instrument(name = "send_magic_packet", skip(packet), fields(mac_address = %packet.mac_address()))]
+pub async fn send_magic_packet(
+    packet: &MagicPacket,
+    target: &WakeTarget,
+) -> Result<WakeReport, WolError> {
+    let bytes_sent = match target {
+        WakeTarget::Udp { address, interface } => {
+            send_udp(packet, *address, interface.as_deref()).await?
+        }
+        WakeTarget::Ethernet { interface } => {
+            let payload = packet.as_bytes().to_vec();
+            let interface = interface.clone();
+            spawn_blocking_with_tracing(move || {
+                ethernet::send_frame(&payload, &interface, &MacAddress::BROADCAST)
+            })
+            .await
+            .map_err(|e| WolError::SendError {
+                target: target.interface().unwrap_or_default().to_string(),
+                source: std::io::Error::other(e),
+            })??
+        }
+    };
+    if bytes_sent != packet.as_bytes().len() {
+        return Err(WolError::Truncated {
+            sent: bytes_sent,
+            expected: packet.as_bytes().len(),
+        });
+    }
+    tracing::info!(
+        "magic packet sent with {:?} to {:?}",
+        target.transport(),
+        target
+    );
+    Ok(WakeReport {
+        mac_address: *packet.mac_address(),
+        secure_on: packet.has_secure_on(),
+        transport: target.transport(),
+        target: match target {
+            WakeTarget::Udp { address, .. } => Some(*address),
+            WakeTarget::Ethernet { .. } => None,
+        },
+        interface: target.interface().map(str::to_string),
+        bytes_sent,
+        sent_at: Utc::now(),
+    })
+}
+
+async fn send_udp(
+    packet: &MagicPacket,
+    address: SocketAddr,
+    interface: Option<&str>,
+) -> Result<usize, WolError> {
+    let send_error = |source| WolError::SendError {
+        target: address.to_string(),
+        source,
+    };
+    let socket = udp_socket(address, interface).map_err(send_error)?;
+    socket
+        .send_to(packet.as_bytes(), address)
+        .await
+        .map_err(send_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sends_magic_packet_to_loopback_listener() {
+        let listener = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let mac_address: MacAddress = "00:11:22:33:44:55".parse().unwrap();
+        let packet = MagicPacket::new(&mac_address);
+
+        let report = send_magic_packet(&packet, &WakeTarget::udp(address, None))
+            .await
+            .unwrap();
+
+        let mut received = [0u8; 256];
+        let (len, _) = listener.recv_from(&mut received).await.unwrap();
+        assert_eq!(&received[..len], packet.as_bytes());
+        assert_eq!(report.bytes_sent, packet.as_bytes().len());
+        assert_eq!(report.transport, Transport::Udp);
+        assert_eq!(report.target, Some(address));
+        assert_eq!(report.mac_address, mac_address);
+    }
+
+    #[test]
+    fn validates_interface_names() {
+        assert!(validate_interface_name("eth0").is_ok());
+        assert!(validate_interface_name("br-lan.20").is_ok());
+        for interface in ["", "eth/0", "eth:0", "a very long name", "sixteen_chars_xx"] {
+            assert!(validate_interface_name(interface).is_err(), "{interface}");
+        }
+    }
+}
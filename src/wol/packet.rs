@@ -0,0 +1,90 @@
+use super::{
+    mac::{MacAddress, MAC_ADDRESS_LEN},
+    secure_on::SecureOn,
+};
+
+const SYNC_STREAM: [u8; 6] = [0xFF; 6];
+const MAC_REPETITIONS: usize = 16;
+pub const MAGIC_PACKET_LEN: usize = SYNC_STREAM.len() + MAC_REPETITIONS * MAC_ADDRESS_LEN;
+
+/// Wake-on-LAN payload: 6 bytes of `0xFF` followed by the target MAC repeated 16 times,
+/// optionally followed by the SecureOn password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagicPacket {
+    mac_address: MacAddress,
+    secure_on: bool,
+    payload: Vec<u8>,
+}
+
+impl MagicPacket {
+    pub fn new(mac_address: &MacAddress) -> Self {
+        let mut payload = Vec::with_capacity(MAGIC_PACKET_LEN);
+        payload.extend_from_slice(&SYNC_STREAM);
+        for _ in 0..MAC_REPETITIONS {
+            payload.extend_from_slice(mac_address.as_bytes());
+        }
+        Self {
+            mac_address: *mac_address,
+            secure_on: false,
+            payload,
+        }
+    }
+
+    pub fn with_secure_on(mac_address: &MacAddress, secure_on: &SecureOn) -> Self {
+        let mut packet = Self::new(mac_address);
+        packet.payload.extend_from_slice(secure_on.as_bytes());
+        packet.secure_on = true;
+        packet
+    }
+
+    pub fn mac_address(&self) -> &MacAddress {
+        &self.mac_address
+    }
+
+    pub fn has_secure_on(&self) -> bool {
+        self.secure_on
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mac_address() -> MacAddress {
+        MacAddress::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55])
+    }
+
+    #[test]
+    fn layout_is_sync_stream_then_16_macs() {
+        let packet = MagicPacket::new(&mac_address());
+        let bytes = packet.as_bytes();
+        assert_eq!(bytes.len(), MAGIC_PACKET_LEN);
+        assert_eq!(bytes.len(), 102);
+        assert_eq!(&bytes[..6], &[0xFF; 6]);
+        for repetition in bytes[6..].chunks(MAC_ADDRESS_LEN) {
+            assert_eq!(repetition, mac_address().as_bytes());
+        }
+        assert!(!packet.has_secure_on());
+    }
+
+    #[test]
+    fn secure_on_is_appended() {
+        let secure_on: SecureOn = "de:ad:be:ef:00:01".parse().unwrap();
+        let packet = MagicPacket::with_secure_on(&mac_address(), &secure_on);
+        let bytes = packet.as_bytes();
+        assert_eq!(bytes.len(), MAGIC_PACKET_LEN + 6);
+        assert_eq!(
+            &bytes[..MAGIC_PACKET_LEN],
+            MagicPacket::new(&mac_address()).as_bytes()
+        );
+        assert_eq!(
+            &bytes[MAGIC_PACKET_LEN..],
+            &[0xde, 0xad, 0xbe, 0xef, 0x00, 0x01]
+        );
+        assert!(packet.has_secure_on());
+    }
+}
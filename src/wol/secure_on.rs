@@ -0,0 +1,169 @@
+use super::{error::WolError, mac::parse_hex_octet};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use std::str::FromStr;
+use uuid::Uuid;
+
+const NONCE_LEN: usize = 12;
+const KEY_SALT: &[u8] = b"wol_server.secure_on";
+
+/// SecureOn password appended to the magic packet, 4 or 6 bytes long.
+///
+/// Parsed from hex octets like a mac address: `aa:bb:cc:dd`, `aa-bb-cc-dd-ee-ff` or `aabbccdd`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecureOn(Vec<u8>);
+
+impl SecureOn {
+    pub fn new(bytes: Vec<u8>) -> Result<Self, WolError> {
+        match bytes.len() {
+            4 | 6 => Ok(Self(bytes)),
+            _ => Err(WolError::InvalidSecureOn),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// never leak the password in logs
+impl std::fmt::Debug for SecureOn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecureOn(***)")
+    }
+}
+
+impl FromStr for SecureOn {
+    type Err = WolError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value: String = value
+            .trim()
+            .chars()
+            .filter(|c| *c != ':' && *c != '-')
+            .collect();
+        if !value.len().is_multiple_of(2) || !value.is_ascii() {
+            return Err(WolError::InvalidSecureOn);
+        }
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|i| parse_hex_octet(&value[i..i + 2]))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(WolError::InvalidSecureOn)?;
+        Self::new(bytes)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SecureOn {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: std::borrow::Cow<str> = serde::Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Encrypts SecureOn passwords before they hit the database.
+///
+/// The key is derived from a configured secret with argon2,
+/// stored values are `nonce || ciphertext`. The device id is authenticated along,
+/// a value copied to another device row doesn't decrypt.
+#[derive(Clone)]
+pub struct SecureOnCipher(ChaCha20Poly1305);
+
+impl SecureOnCipher {
+    pub fn from_secret(secret: &str) -> Result<Self, WolError> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(secret.as_bytes(), KEY_SALT, &mut key)
+            .map_err(|_| WolError::SecureOnCipher)?;
+        Ok(Self(ChaCha20Poly1305::new(&key)))
+    }
+
+    pub fn encrypt(&self, device_id: Uuid, secure_on: &SecureOn) -> Result<Vec<u8>, WolError> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: secure_on.as_bytes(),
+            aad: device_id.as_bytes(),
+        };
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, payload)
+            .map_err(|_| WolError::SecureOnCipher)?;
+        let mut stored = nonce.to_vec();
+        stored.extend(ciphertext);
+        Ok(stored)
+    }
+
+    pub fn decrypt(&self, device_id: Uuid, stored: &[u8]) -> Result<SecureOn, WolError> {
+        if stored.len() <= NONCE_LEN {
+            return Err(WolError::SecureOnCipher);
+        }
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: device_id.as_bytes(),
+        };
+        let plaintext = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| WolError::SecureOnCipher)?;
+        SecureOn::new(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secure_on() -> SecureOn {
+        "de:ad:be:ef".parse().unwrap()
+    }
+
+    #[test]
+    fn parses_4_and_6_bytes() {
+        assert_eq!(secure_on().as_bytes(), &[0xde, 0xad, 0xbe, 0xef]);
+        let secure_on: SecureOn = "01-02-03-04-05-06".parse().unwrap();
+        assert_eq!(secure_on.as_bytes(), &[1, 2, 3, 4, 5, 6]);
+        for value in ["", "deadbe", "deadbeef00", "de:ad:be:+f", "zz:ad:be:ef"] {
+            assert!(value.parse::<SecureOn>().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn roundtrips() {
+        let cipher = SecureOnCipher::from_secret("secret").unwrap();
+        let device_id = Uuid::now_v7();
+        let stored = cipher.encrypt(device_id, &secure_on()).unwrap();
+        assert_ne!(&stored[NONCE_LEN..], secure_on().as_bytes());
+        assert_eq!(cipher.decrypt(device_id, &stored).unwrap(), secure_on());
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let device_id = Uuid::now_v7();
+        let stored = SecureOnCipher::from_secret("secret")
+            .unwrap()
+            .encrypt(device_id, &secure_on())
+            .unwrap();
+        let other = SecureOnCipher::from_secret("other secret").unwrap();
+        assert!(other.decrypt(device_id, &stored).is_err());
+    }
+
+    #[test]
+    fn copied_to_another_device_fails() {
+        let cipher = SecureOnCipher::from_secret("secret").unwrap();
+        let stored = cipher.encrypt(Uuid::now_v7(), &secure_on()).unwrap();
+        assert!(cipher.decrypt(Uuid::now_v7(), &stored).is_err());
+    }
+
+    #[test]
+    fn truncated_value_fails() {
+        let cipher = SecureOnCipher::from_secret("secret").unwrap();
+        assert!(cipher.decrypt(Uuid::now_v7(), &[0; NONCE_LEN]).is_err());
+    }
+}